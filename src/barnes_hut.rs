@@ -0,0 +1,326 @@
+use nalgebra::Vector2;
+
+use crate::CelestialBody;
+use crate::gravity::{CompensatedSum, GravitySettings, per_body};
+
+/// Depth at which a quadrant stops splitting and just collects bodies, so that
+/// coincident positions can't recurse forever.
+const MAX_DEPTH: u32 = 24;
+
+enum NodeKind {
+    Empty,
+    Leaf(Vec<usize>),
+    Internal([usize; 4]),
+}
+
+/// Total mass and center of mass of one kind of body in a node.
+#[derive(Clone, Copy)]
+struct Aggregate {
+    mass: f32,
+    center_of_mass: Vector2<f32>,
+}
+
+struct Node {
+    center: Vector2<f32>,
+    half_size: f32,
+    /// Ordinary bodies and repulsive ones are summarized separately, since they act on
+    /// others with opposite signs.
+    attractive: Aggregate,
+    repulsive: Aggregate,
+    kind: NodeKind,
+}
+
+impl Node {
+    fn new(center: Vector2<f32>, half_size: f32) -> Self {
+        let empty = Aggregate {
+            mass: 0.0,
+            center_of_mass: center,
+        };
+        Self {
+            center,
+            half_size,
+            attractive: empty,
+            repulsive: empty,
+            kind: NodeKind::Empty,
+        }
+    }
+
+    fn contains(&self, p: Vector2<f32>) -> bool {
+        (p.x - self.center.x).abs() <= self.half_size
+            && (p.y - self.center.y).abs() <= self.half_size
+    }
+
+    fn quadrant(&self, p: Vector2<f32>) -> usize {
+        usize::from(p.x >= self.center.x) | (usize::from(p.y >= self.center.y) << 1)
+    }
+}
+
+/// Quadtree over body positions, storing each node's total mass and center of mass.
+pub struct QuadTree {
+    nodes: Vec<Node>,
+}
+
+impl QuadTree {
+    pub fn build(bodies: &[CelestialBody], positions: &[Vector2<f32>]) -> Self {
+        let mut min = Vector2::repeat(f32::INFINITY);
+        let mut max = Vector2::repeat(f32::NEG_INFINITY);
+        // Diverged bodies are left out rather than blowing up the bounds.
+        let finite: Vec<usize> = (0..positions.len())
+            .filter(|&i| positions[i].iter().all(|x| x.is_finite()))
+            .collect();
+        for &i in &finite {
+            min = min.inf(&positions[i]);
+            max = max.sup(&positions[i]);
+        }
+
+        let mut tree = Self { nodes: Vec::new() };
+        if finite.is_empty() {
+            return tree;
+        }
+
+        let extent = max - min;
+        let half_size = extent.x.max(extent.y) / 2.0 + 1.0;
+        tree.nodes.push(Node::new((min + max) / 2.0, half_size));
+        for i in finite {
+            tree.insert(0, i, positions, 0);
+        }
+        tree.summarize(0, bodies, positions);
+        tree
+    }
+
+    fn insert(&mut self, node: usize, body: usize, positions: &[Vector2<f32>], depth: u32) {
+        let quadrant = self.nodes[node].quadrant(positions[body]);
+        match &mut self.nodes[node].kind {
+            NodeKind::Empty => self.nodes[node].kind = NodeKind::Leaf(vec![body]),
+            NodeKind::Leaf(bodies) if depth >= MAX_DEPTH => bodies.push(body),
+            NodeKind::Leaf(bodies) => {
+                let existing = std::mem::take(bodies);
+                self.subdivide(node);
+                for other in existing {
+                    self.insert(node, other, positions, depth);
+                }
+                self.insert(node, body, positions, depth);
+            }
+            NodeKind::Internal(children) => {
+                let child = children[quadrant];
+                self.insert(child, body, positions, depth + 1);
+            }
+        }
+    }
+
+    fn subdivide(&mut self, node: usize) {
+        let center = self.nodes[node].center;
+        let quarter = self.nodes[node].half_size / 2.0;
+        let mut children = [0; 4];
+        for (quadrant, child) in children.iter_mut().enumerate() {
+            let dx = if quadrant & 1 == 0 { -quarter } else { quarter };
+            let dy = if quadrant & 2 == 0 { -quarter } else { quarter };
+            *child = self.nodes.len();
+            self.nodes
+                .push(Node::new(center + Vector2::new(dx, dy), quarter));
+        }
+        self.nodes[node].kind = NodeKind::Internal(children);
+    }
+
+    /// Fills in masses and centers of mass bottom-up, returning
+    /// `[(mass, mass-weighted position); 2]` for attractive then repulsive bodies.
+    fn summarize(
+        &mut self,
+        node: usize,
+        bodies: &[CelestialBody],
+        positions: &[Vector2<f32>],
+    ) -> [(f32, Vector2<f32>); 2] {
+        let mut sums = [(0.0, Vector2::zeros()); 2];
+        match &self.nodes[node].kind {
+            NodeKind::Empty => {}
+            NodeKind::Leaf(members) => {
+                for &i in members {
+                    let sum = &mut sums[usize::from(bodies[i].repulsive)];
+                    sum.0 += bodies[i].mass;
+                    sum.1 += positions[i] * bodies[i].mass;
+                }
+            }
+            NodeKind::Internal(children) => {
+                let children = *children;
+                for child in children {
+                    let child_sums = self.summarize(child, bodies, positions);
+                    for (sum, (m, w)) in sums.iter_mut().zip(child_sums) {
+                        sum.0 += m;
+                        sum.1 += w;
+                    }
+                }
+            }
+        }
+
+        let n = &mut self.nodes[node];
+        for (aggregate, (mass, weighted)) in
+            [&mut n.attractive, &mut n.repulsive].into_iter().zip(sums)
+        {
+            aggregate.mass = mass;
+            if mass > 0.0 {
+                aggregate.center_of_mass = weighted / mass;
+            }
+        }
+        sums
+    }
+
+    /// Approximate acceleration on body `i`. A node is treated as a point mass when
+    /// its width over its distance falls below `gravity.theta`; individual bodies always go
+    /// through the exact pairwise force. Nodes are visited in a fixed order for a given
+    /// tree, so the result is reproducible.
+    pub fn acceleration(
+        &self,
+        bodies: &[CelestialBody],
+        positions: &[Vector2<f32>],
+        i: usize,
+        gravity: &GravitySettings,
+    ) -> Vector2<f32> {
+        let p = positions[i];
+        let mut acc = CompensatedSum::default();
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            match &node.kind {
+                NodeKind::Empty => {}
+                NodeKind::Leaf(members) => {
+                    for &j in members.iter().filter(|&&j| j != i) {
+                        acc = acc.add(bodies[i].acceleration_from(
+                            p,
+                            &bodies[j],
+                            positions[j],
+                            gravity,
+                        ));
+                    }
+                }
+                NodeKind::Internal(children) => {
+                    // Opened on the distance to the combined center of mass.
+                    let total = node.attractive.mass + node.repulsive.mass;
+                    let center = (node.attractive.center_of_mass * node.attractive.mass
+                        + node.repulsive.center_of_mass * node.repulsive.mass)
+                        / total.max(f32::MIN_POSITIVE);
+                    let dist = (center - p).norm();
+                    if !node.contains(p) && node.half_size * 2.0 < gravity.theta * dist {
+                        // A repulsive body is pushed away by everything.
+                        let sign = if bodies[i].repulsive { -1.0 } else { 1.0 };
+                        let Aggregate {
+                            mass,
+                            center_of_mass,
+                        } = node.attractive;
+                        acc = acc.add(gravity.pull(center_of_mass - p, mass) * sign);
+                        let Aggregate {
+                            mass,
+                            center_of_mass,
+                        } = node.repulsive;
+                        acc = acc.add(-gravity.pull(center_of_mass - p, mass));
+                    } else {
+                        stack.extend_from_slice(children);
+                    }
+                }
+            }
+        }
+        acc.total()
+    }
+}
+
+pub fn accelerations(
+    bodies: &[CelestialBody],
+    positions: &[Vector2<f32>],
+    gravity: &GravitySettings,
+) -> Vec<Vector2<f32>> {
+    let tree = QuadTree::build(bodies, positions);
+    per_body(bodies.len(), |i| {
+        tree.acceleration(bodies, positions, i, gravity)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ecolor::Color32;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::gravity::pairwise_accelerations;
+
+    fn scattered(count: usize) -> Vec<CelestialBody> {
+        let mut rng = StdRng::seed_from_u64(3);
+        (0..count)
+            .map(|_| {
+                let pos = Vector2::new(
+                    rng.random_range(-500.0..500.0),
+                    rng.random_range(-500.0..500.0),
+                );
+                CelestialBody::new(pos, rng.random_range(1.0..100.0), Color32::WHITE)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_pairwise_at_small_theta() {
+        let bodies = scattered(300);
+        let positions: Vec<_> = bodies.iter().map(|b| b.pos).collect();
+        let gravity = GravitySettings {
+            theta: 0.1,
+            ..GravitySettings::default()
+        };
+
+        let exact = pairwise_accelerations(&bodies, &positions, &gravity);
+        let approx = accelerations(&bodies, &positions, &gravity);
+
+        for (i, (e, a)) in exact.iter().zip(&approx).enumerate() {
+            assert!(
+                (e - a).norm() <= 1e-2 * e.norm(),
+                "body {i}: exact {e:?}, Barnes-Hut {a:?}"
+            );
+        }
+    }
+
+    type AccelerationFn =
+        fn(&[CelestialBody], &[Vector2<f32>], &GravitySettings) -> Vec<Vector2<f32>>;
+
+    /// Steps a repulsive pair starting `distance` apart at rest, asserting the gap never
+    /// shrinks and ends up wider.
+    fn assert_separates(accelerations: AccelerationFn, distance: f32) {
+        let gravity = GravitySettings::default();
+        let mut bodies = vec![
+            CelestialBody::new(Vector2::zeros(), 1.0e7, Color32::WHITE),
+            CelestialBody::new(Vector2::new(distance, 0.0), 50.0, Color32::WHITE),
+        ];
+        bodies[0].repulsive = true;
+        for body in &mut bodies {
+            body.radius = 0.1;
+        }
+        let mut gap = distance;
+        for step in 0..500 {
+            let positions: Vec<_> = bodies.iter().map(|b| b.pos).collect();
+            let acc = accelerations(&bodies, &positions, &gravity);
+            for (body, a) in bodies.iter_mut().zip(acc) {
+                body.vel += a * 0.1;
+                body.pos += body.vel * 0.1;
+            }
+            let next = (bodies[1].pos - bodies[0].pos).norm();
+            assert!(
+                next >= gap,
+                "gap shrank to {next} at step {step} from {distance}"
+            );
+            gap = next;
+        }
+        assert!(
+            gap > distance,
+            "a pair starting {distance} apart never moved"
+        );
+    }
+
+    #[test]
+    fn repulsive_pairs_separate() {
+        for distance in [0.5, 3.0, 40.0, 900.0] {
+            assert_separates(pairwise_accelerations, distance);
+            assert_separates(accelerations, distance);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! A single body and how its size follows from its mass.
+
+use std::collections::VecDeque;
+
+use ecolor::Color32;
+use nalgebra::Vector2;
+use serde::{Deserialize, Serialize};
+
+use crate::gravity::{
+    GravitySettings, ORBIT_ASSIST_ACCEL, ORBIT_ASSIST_MASS_RATIO, ORBIT_ASSIST_RANGE,
+};
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct CelestialBody {
+    pub pos: Vector2<f32>,
+    pub vel: Vector2<f32>,
+    pub mass: f32,
+    pub radius: f32,
+    /// Mass per unit area; together with the mass it sets the radius.
+    #[serde(default = "default_density")]
+    pub density: f32,
+    pub color: Color32,
+    /// Anchored bodies pull on others but never move themselves.
+    #[serde(default)]
+    pub fixed: bool,
+    /// Escaped the system; see `SimState::detect_ejections`.
+    #[serde(default)]
+    pub ejected: bool,
+    /// Pushes every other body away, and is pushed away in turn, with the strength
+    /// gravity would otherwise pull.
+    #[serde(default)]
+    pub repulsive: bool,
+    #[serde(default)]
+    pub shape: BodyShape,
+    /// Numbered by `SimState` and kept for the body's whole life, unlike its index, which
+    /// shifts when earlier bodies merge or are removed.
+    #[serde(skip)]
+    pub id: u64,
+    /// Recent positions, oldest first.
+    #[serde(skip)]
+    pub trail: VecDeque<Vector2<f32>>,
+}
+
+impl CelestialBody {
+    pub fn new(pos: Vector2<f32>, mass: f32, color: Color32) -> Self {
+        Self {
+            pos,
+            vel: Vector2::zeros(),
+            mass,
+            radius: radius_for_mass(mass, DEFAULT_DENSITY),
+            density: DEFAULT_DENSITY,
+            color,
+            fixed: false,
+            ejected: false,
+            repulsive: false,
+            shape: BodyShape::Disc,
+            id: 0,
+            trail: VecDeque::new(),
+        }
+    }
+
+    pub fn record_trail(&mut self, max_len: usize) {
+        self.trail.push_back(self.pos);
+        while self.trail.len() > max_len {
+            self.trail.pop_front();
+        }
+    }
+
+    /// Recomputes the radius from the mass and density.
+    pub fn resize(&mut self, radius_mode: RadiusMode) {
+        self.radius = radius_mode.radius(self.mass, self.density);
+    }
+
+    /// `1.0` if the pair attracts, `-1.0` if either body is repulsive.
+    pub(crate) fn interaction_sign(&self, other: &CelestialBody) -> f32 {
+        if self.repulsive || other.repulsive {
+            -1.0
+        } else {
+            1.0
+        }
+    }
+
+    pub(crate) fn overlaps(&self, other: &CelestialBody) -> bool {
+        (other.pos - self.pos).norm_squared() < (self.radius + other.radius).powi(2)
+    }
+
+    /// Merges `other` into `self`, conserving mass and momentum. If either body is
+    /// fixed, the result stays pinned where that body was.
+    pub fn absorb(&mut self, other: &CelestialBody, radius_mode: RadiusMode) {
+        let total = self.mass + other.mass;
+        if self.fixed || other.fixed {
+            if !self.fixed {
+                self.pos = other.pos;
+            }
+            self.vel = Vector2::zeros();
+            self.fixed = true;
+        } else {
+            self.pos = (self.pos * self.mass + other.pos * other.mass) / total;
+            self.vel = (self.vel * self.mass + other.vel * other.mass) / total;
+        }
+        self.color = blend_colors(self.color, other.color, other.mass / total);
+        // The heavier body decides whether the merged one attracts or repels, and its look.
+        if other.mass > self.mass {
+            self.repulsive = other.repulsive;
+            self.shape = other.shape;
+        }
+        // Areas add, so the merged density is the total mass over the total area.
+        self.density = total / (self.mass / self.density + other.mass / other.density);
+        self.mass = total;
+        self.resize(radius_mode);
+    }
+
+    /// Acceleration this body feels from `other` when they sit at `pos` and `other_pos`.
+    pub(crate) fn acceleration_from(
+        &self,
+        pos: Vector2<f32>,
+        other: &CelestialBody,
+        other_pos: Vector2<f32>,
+        gravity: &GravitySettings,
+    ) -> Vector2<f32> {
+        let dir = other_pos - pos;
+        let dist_sq = dir.norm_squared();
+        let dist = dist_sq.sqrt();
+        let mut acc = Vector2::zeros();
+
+        // A diverged body is about to be scrubbed; don't let its infinities spread first.
+        if dist_sq > (self.radius + other.radius).powi(2) && dist_sq.is_finite() {
+            let sign = self.interaction_sign(other);
+            acc += gravity.pull(dir, other.mass) * sign;
+
+            if gravity.auto_orbit_assist
+                && sign > 0.0
+                && dist < ORBIT_ASSIST_RANGE
+                && other.mass > self.mass * ORBIT_ASSIST_MASS_RATIO
+            {
+                let tangential = Vector2::new(-dir.y, dir.x).normalize();
+                acc += tangential * ORBIT_ASSIST_ACCEL;
+            }
+        }
+        acc
+    }
+}
+
+/// Density that reproduces the original `sqrt(mass / PI) / 2` radius.
+pub const DEFAULT_DENSITY: f32 = 4.0;
+
+fn default_density() -> f32 {
+    DEFAULT_DENSITY
+}
+
+/// Radius of a disc of the given mass and areal density.
+pub fn radius_for_mass(mass: f32, density: f32) -> f32 {
+    (mass / (density * std::f32::consts::PI)).sqrt()
+}
+
+/// How a body is drawn. Only the picture changes; collisions always use the radius.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BodyShape {
+    #[default]
+    Disc,
+    /// Hollow circle, for gas giants.
+    Ring,
+    /// Bright core with rays, for luminous bodies.
+    Star,
+}
+
+impl BodyShape {
+    pub const ALL: [BodyShape; 3] = [BodyShape::Disc, BodyShape::Ring, BodyShape::Star];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BodyShape::Disc => "Disc",
+            BodyShape::Ring => "Ring",
+            BodyShape::Star => "Star",
+        }
+    }
+}
+
+/// How a body's mass maps to its radius, which is both its size on screen and its
+/// collision radius.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RadiusMode {
+    /// Mass proportional to area, the original formula.
+    Area,
+    /// Mass proportional to volume, as for a uniform-density sphere.
+    Volume,
+    /// Radius grows with the log of the mass, so stars and asteroids both stay visible.
+    /// Ignores density.
+    Logarithmic,
+}
+
+impl RadiusMode {
+    pub const ALL: [RadiusMode; 3] = [
+        RadiusMode::Area,
+        RadiusMode::Volume,
+        RadiusMode::Logarithmic,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RadiusMode::Area => "Area",
+            RadiusMode::Volume => "Volume",
+            RadiusMode::Logarithmic => "Logarithmic",
+        }
+    }
+
+    pub fn radius(self, mass: f32, density: f32) -> f32 {
+        match self {
+            RadiusMode::Area => radius_for_mass(mass, density),
+            RadiusMode::Volume => {
+                (mass * 3.0 / (4.0 * std::f32::consts::PI) * DEFAULT_DENSITY / density).cbrt()
+            }
+            RadiusMode::Logarithmic => 1.6 * mass.ln_1p(),
+        }
+    }
+}
+
+/// Linear blend from `a` towards `b` by `t` in `0.0..=1.0`.
+pub fn blend_colors(a: Color32, b: Color32, t: f32) -> Color32 {
+    let mix = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t).round() as u8;
+    Color32::from_rgb(mix(a.r(), b.r()), mix(a.g(), b.g()), mix(a.b(), b.b()))
+}
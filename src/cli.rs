@@ -0,0 +1,131 @@
+//! Command-line options. Everything is optional; absent flags keep the built-in defaults.
+
+use egui_interplanetary_simulation::Scenario;
+
+pub const USAGE: &str = "\
+usage: egui_interplanetary_simulation [OPTIONS]
+
+  --width W          initial window width (default 1500)
+  --height H         initial window height (default 1200)
+  --title TITLE      window title
+  --scenario NAME    asteroid-belt, solar-system, binary-stars, figure-eight,
+                     slingshot, cluster or planets
+  --seed SEED        seed for the scenario's random choices
+  --scene PATH       start from a scene saved with the Save button
+  --bench STEPS      run STEPS physics steps without a window and print timings
+  --report-probe     with --bench, also print the scenario probe's speed before and after
+  --help             print this message";
+
+pub struct CliArgs {
+    pub width: f32,
+    pub height: f32,
+    pub title: String,
+    pub scenario: Option<Scenario>,
+    pub seed: Option<u64>,
+    pub scene: Option<String>,
+    pub bench: Option<u64>,
+    pub report_probe: bool,
+    pub help: bool,
+}
+
+impl Default for CliArgs {
+    fn default() -> Self {
+        Self {
+            width: 1500.0,
+            height: 1200.0,
+            title: "Interplanetary Simulation".to_owned(),
+            scenario: None,
+            seed: None,
+            scene: None,
+            bench: None,
+            report_probe: false,
+            help: false,
+        }
+    }
+}
+
+impl CliArgs {
+    /// Parses the arguments after the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli = Self::default();
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{flag} needs a value"));
+            match flag.as_str() {
+                "--width" => cli.width = parse_size(&flag, value()?)?,
+                "--height" => cli.height = parse_size(&flag, value()?)?,
+                "--title" => cli.title = value()?,
+                "--scenario" => {
+                    let name = value()?;
+                    cli.scenario = Some(
+                        Scenario::from_name(&name)
+                            .ok_or_else(|| format!("unknown scenario {name:?}"))?,
+                    );
+                }
+                "--seed" => cli.seed = Some(parse_number(&flag, value()?)?),
+                "--scene" => cli.scene = Some(value()?),
+                "--bench" => cli.bench = Some(parse_number(&flag, value()?)?),
+                "--report-probe" => cli.report_probe = true,
+                "--help" | "-h" => cli.help = true,
+                _ => return Err(format!("unknown argument {flag:?}")),
+            }
+        }
+        Ok(cli)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: String) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{flag} expects a number, got {value:?}"))
+}
+
+/// A window dimension: a finite number of points above zero.
+fn parse_size(flag: &str, value: String) -> Result<f32, String> {
+    let size: f32 = parse_number(flag, value)?;
+    if size.is_finite() && size > 0.0 {
+        Ok(size)
+    } else {
+        Err(format!("{flag} expects a positive size, got {size}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliArgs, String> {
+        CliArgs::parse(args.iter().map(|&a| a.to_owned()))
+    }
+
+    #[test]
+    fn no_arguments_keep_the_defaults() {
+        let cli = parse(&[]).unwrap();
+        assert_eq!((cli.width, cli.height), (1500.0, 1200.0));
+        assert_eq!(cli.scenario, None);
+        assert_eq!(cli.bench, None);
+        assert!(!cli.help);
+    }
+
+    #[test]
+    fn flags_set_their_fields() {
+        let cli = parse(&["--width", "800", "--scenario", "cluster", "--seed", "7"]).unwrap();
+        assert_eq!(cli.width, 800.0);
+        assert_eq!(cli.scenario, Some(Scenario::Cluster));
+        assert_eq!(cli.seed, Some(7));
+    }
+
+    #[test]
+    fn bad_values_and_unknown_flags_are_rejected() {
+        for size in ["-5", "0", "NaN", "inf", "wide"] {
+            assert!(parse(&["--width", size]).is_err(), "--width {size}");
+            assert!(parse(&["--height", size]).is_err(), "--height {size}");
+        }
+        assert!(parse(&["--seed", "-1"]).is_err());
+        assert!(parse(&["--bench"]).is_err());
+        assert_eq!(
+            parse(&["--fullscreen"]).err().as_deref(),
+            Some("unknown argument \"--fullscreen\"")
+        );
+    }
+}
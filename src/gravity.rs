@@ -0,0 +1,298 @@
+//! Newtonian gravity between bodies, summed exactly or through a Barnes-Hut tree.
+
+use nalgebra::Vector2;
+use serde::{Deserialize, Serialize};
+
+use crate::CelestialBody;
+use crate::barnes_hut;
+
+/// Default gravitational constant, in arbitrary sim units.
+pub const G: f32 = 6.67430e-5;
+/// Above this many bodies the gravity pass switches from brute force to Barnes-Hut.
+pub const BARNES_HUT_THRESHOLD: usize = 256;
+
+/// Velocity, relative to the central body, of a circular orbit through `body_pos`:
+/// `sqrt(G*M/r)` along the counter-clockwise (on screen) perpendicular of the radius.
+pub fn circular_orbit_velocity(
+    central_mass: f32,
+    central_pos: Vector2<f32>,
+    body_pos: Vector2<f32>,
+    g: f32,
+) -> Vector2<f32> {
+    let offset = body_pos - central_pos;
+    let r = offset.norm();
+    if r == 0.0 {
+        return Vector2::zeros();
+    }
+    Vector2::new(-offset.y, offset.x) / r * (g * central_mass / r).sqrt()
+}
+
+/// Roughly the old 0.05-per-frame tangential kick, expressed per second at 60 Hz.
+pub(crate) const ORBIT_ASSIST_ACCEL: f32 = 3.0;
+/// The orbit assist only kicks in this close to the heavier body...
+pub(crate) const ORBIT_ASSIST_RANGE: f32 = 150.0;
+/// ...and only when it is more than this many times heavier.
+pub(crate) const ORBIT_ASSIST_MASS_RATIO: f32 = 5.0;
+
+/// Evaluates `f` for every body index, in parallel when the `rayon` feature is on.
+/// Results are always returned in index order, and since each call runs on one thread,
+/// they are bitwise the same with or without `rayon` as long as `f` is deterministic.
+pub(crate) fn per_body<F>(count: usize, f: F) -> Vec<Vector2<f32>>
+where
+    F: Fn(usize) -> Vector2<f32> + Sync + Send,
+{
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        (0..count).into_par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        (0..count).map(f).collect()
+    }
+}
+
+/// Exact O(n²) gravitational acceleration of every body placed at `positions`.
+pub(crate) fn pairwise_accelerations(
+    bodies: &[CelestialBody],
+    positions: &[Vector2<f32>],
+    gravity: &GravitySettings,
+) -> Vec<Vector2<f32>> {
+    per_body(bodies.len(), |i| {
+        bodies
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(j, other)| {
+                bodies[i].acceleration_from(positions[i], other, positions[j], gravity)
+            })
+            .fold(CompensatedSum::default(), CompensatedSum::add)
+            .total()
+    })
+}
+
+/// Running vector sum with Neumaier compensation, so the many tiny pulls of far-away
+/// bodies aren't rounded away next to one large pull. Terms are still added in the order
+/// given, so the result only depends on that order.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct CompensatedSum {
+    sum: Vector2<f32>,
+    /// Low-order bits lost so far, added back by [`Self::total`].
+    compensation: Vector2<f32>,
+}
+
+impl CompensatedSum {
+    pub(crate) fn add(mut self, term: Vector2<f32>) -> Self {
+        for k in 0..2 {
+            let (sum, x) = (self.sum[k], term[k]);
+            let t = sum + x;
+            self.compensation[k] += if sum.abs() >= x.abs() {
+                (sum - t) + x
+            } else {
+                (x - t) + sum
+            };
+            self.sum[k] = t;
+        }
+        self
+    }
+
+    pub(crate) fn total(self) -> Vector2<f32> {
+        self.sum + self.compensation
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GravitySettings {
+    /// Strength of gravity; scenarios compute their orbital speeds from the default [`G`].
+    pub gravity_constant: f32,
+    /// Barnes-Hut opening angle; smaller is more accurate and slower.
+    pub theta: f32,
+    /// Softening length ε. Force magnitude is `G*m1*m2 / (r² + ε²)`, which stays
+    /// bounded at tiny separations; `0.0` recovers exact Newtonian gravity.
+    pub softening: f32,
+    /// Non-physical helper that nudges light bodies into orbit: a body within
+    /// [`ORBIT_ASSIST_RANGE`] of one [`ORBIT_ASSIST_MASS_RATIO`] times heavier also gets
+    /// a constant [`ORBIT_ASSIST_ACCEL`] counter-clockwise around it. It adds energy, and
+    /// under Barnes-Hut only applies to bodies close enough to be summed individually.
+    #[serde(default)]
+    pub auto_orbit_assist: bool,
+}
+
+impl Default for GravitySettings {
+    fn default() -> Self {
+        Self {
+            gravity_constant: G,
+            theta: 0.5,
+            softening: 1.0,
+            auto_orbit_assist: false,
+        }
+    }
+}
+
+impl GravitySettings {
+    /// Softened acceleration towards a point `mass` sitting at offset `dir`.
+    pub fn pull(&self, dir: Vector2<f32>, mass: f32) -> Vector2<f32> {
+        let dist_sq = dir.norm_squared();
+        dir.normalize()
+            * (self.gravity_constant * mass / (dist_sq + self.softening * self.softening))
+    }
+
+    /// Potential energy of a pair, consistent with the softened force in [`Self::pull`].
+    pub fn pair_potential(&self, m1: f32, m2: f32, dist: f32) -> f32 {
+        let g = self.gravity_constant;
+        let eps = self.softening;
+        if eps > 0.0 {
+            -g * m1 * m2 / eps * (std::f32::consts::FRAC_PI_2 - (dist / eps).atan())
+        } else {
+            -g * m1 * m2 / dist
+        }
+    }
+
+    /// Gravitational acceleration of every body, evaluated with the bodies placed at `positions`.
+    pub fn accelerations(
+        &self,
+        bodies: &[CelestialBody],
+        positions: &[Vector2<f32>],
+    ) -> Vec<Vector2<f32>> {
+        let mut acc = if bodies.len() > BARNES_HUT_THRESHOLD {
+            barnes_hut::accelerations(bodies, positions, self)
+        } else {
+            pairwise_accelerations(bodies, positions, self)
+        };
+        for (a, body) in acc.iter_mut().zip(bodies) {
+            if body.fixed {
+                *a = Vector2::zeros();
+            }
+        }
+        acc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ecolor::Color32;
+
+    use super::*;
+    use crate::Integrator;
+
+    fn body(x: f32, y: f32, mass: f32) -> CelestialBody {
+        CelestialBody::new(Vector2::new(x, y), mass, Color32::WHITE)
+    }
+
+    #[test]
+    fn step_accumulates_the_pairwise_sum() {
+        let mut bodies = vec![
+            body(0.0, 0.0, 5000.0),
+            body(120.0, 0.0, 300.0),
+            body(-40.0, 90.0, 800.0),
+            body(60.0, -75.0, 50.0),
+        ];
+        let before = bodies.clone();
+        let gravity = GravitySettings::default();
+        let dt = 0.01;
+
+        let acc = Integrator::SemiImplicitEuler.step(&mut bodies, dt, &gravity);
+
+        for (i, a) in before.iter().enumerate() {
+            let mut expected = Vector2::zeros();
+            for (_, b) in before.iter().enumerate().filter(|&(j, _)| j != i) {
+                let dir = b.pos - a.pos;
+                let dist_sq = dir.norm_squared();
+                let eps_sq = gravity.softening * gravity.softening;
+                expected += dir / dist_sq.sqrt() * (G * b.mass / (dist_sq + eps_sq));
+            }
+            assert!(
+                (acc[i] - expected).norm() <= 1e-5 * expected.norm(),
+                "body {i}: {:?} vs {expected:?}",
+                acc[i]
+            );
+            let dv = bodies[i].vel - a.vel;
+            assert!((dv - expected * dt).norm() <= 1e-5 * expected.norm() * dt);
+        }
+    }
+
+    /// Highest speed a light body reaches flying past a heavy one at a distance of 0.01.
+    fn near_miss_peak_speed(softening: f32) -> f32 {
+        let gravity = GravitySettings {
+            softening,
+            ..GravitySettings::default()
+        };
+        let mut heavy = body(0.0, 0.0, 1.0e5);
+        let mut light = body(-5.0, 0.01, 1.0);
+        // Point-sized, so the pair never overlaps and gravity stays on throughout.
+        heavy.radius = 0.0;
+        light.radius = 0.0;
+        heavy.fixed = true;
+        light.vel.x = 10.0;
+        let mut bodies = vec![heavy, light];
+        let mut peak: f32 = 0.0;
+        for _ in 0..1000 {
+            Integrator::SemiImplicitEuler.step(&mut bodies, 0.001, &gravity);
+            peak = peak.max(bodies[1].vel.norm());
+        }
+        peak
+    }
+
+    #[test]
+    fn softening_bounds_near_miss_kicks() {
+        assert!(near_miss_peak_speed(0.0) > 100.0);
+        assert!(near_miss_peak_speed(1.0) < 15.0);
+    }
+
+    /// Meaningful with `--features rayon`; without it both sides run serially.
+    #[test]
+    fn parallel_accelerations_match_serial() {
+        let bodies: Vec<_> = (0..200)
+            .map(|i| {
+                let angle = i as f32 * 0.7;
+                let r = 20.0 + i as f32 * 3.0;
+                body(
+                    r * angle.cos(),
+                    r * angle.sin(),
+                    1.0 + (i % 7) as f32 * 10.0,
+                )
+            })
+            .collect();
+        let positions: Vec<_> = bodies.iter().map(|b| b.pos).collect();
+        let gravity = GravitySettings::default();
+
+        let parallel = pairwise_accelerations(&bodies, &positions, &gravity);
+        let serial: Vec<_> = (0..bodies.len())
+            .map(|i| {
+                bodies
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(j, other)| {
+                        bodies[i].acceleration_from(positions[i], other, positions[j], &gravity)
+                    })
+                    .fold(CompensatedSum::default(), CompensatedSum::add)
+                    .total()
+            })
+            .collect();
+
+        assert_eq!(parallel, serial);
+    }
+
+    #[test]
+    fn circular_orbit_velocity_keeps_the_distance() {
+        let gravity = GravitySettings {
+            softening: 0.0,
+            ..GravitySettings::default()
+        };
+        let mut sun = body(0.0, 0.0, 1.0e6);
+        sun.radius = 5.0;
+        sun.fixed = true;
+        let mut planet = body(-60.0, 80.0, 1.0);
+        planet.vel = circular_orbit_velocity(sun.mass, sun.pos, planet.pos, G);
+        assert!((planet.vel.norm() - (G * sun.mass / 100.0).sqrt()).abs() < 1e-6);
+        assert!(planet.vel.dot(&planet.pos).abs() < 1e-4);
+        let mut bodies = vec![sun, planet];
+
+        for step in 0..20_000 {
+            Integrator::SemiImplicitEuler.step(&mut bodies, 0.05, &gravity);
+            let r = bodies[1].pos.norm();
+            assert!((r - 100.0).abs() < 1.0, "radius {r} after {step} steps");
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Time-stepping schemes that advance bodies under gravity.
+
+use nalgebra::Vector2;
+use serde::{Deserialize, Serialize};
+
+use crate::{CelestialBody, GravitySettings};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Integrator {
+    ExplicitEuler,
+    SemiImplicitEuler,
+    VelocityVerlet,
+    Rk4,
+}
+
+impl Integrator {
+    pub const ALL: [Integrator; 4] = [
+        Integrator::ExplicitEuler,
+        Integrator::SemiImplicitEuler,
+        Integrator::VelocityVerlet,
+        Integrator::Rk4,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Integrator::ExplicitEuler => "Explicit Euler",
+            Integrator::SemiImplicitEuler => "Semi-implicit Euler",
+            Integrator::VelocityVerlet => "Velocity Verlet",
+            Integrator::Rk4 => "RK4",
+        }
+    }
+
+    /// Advances every body by `dt` seconds, returning the accelerations the bodies had at
+    /// the start of the step.
+    pub fn step(
+        self,
+        bodies: &mut [CelestialBody],
+        dt: f32,
+        gravity: &GravitySettings,
+    ) -> Vec<Vector2<f32>> {
+        // Fixed bodies get zero velocity and zero acceleration, so every scheme leaves them put.
+        for body in bodies.iter_mut().filter(|b| b.fixed) {
+            body.vel = Vector2::zeros();
+        }
+        let pos: Vec<_> = bodies.iter().map(|b| b.pos).collect();
+        let vel: Vec<_> = bodies.iter().map(|b| b.vel).collect();
+        let acc = gravity.accelerations(bodies, &pos);
+
+        match self {
+            Integrator::ExplicitEuler => {
+                for (i, body) in bodies.iter_mut().enumerate() {
+                    body.pos += vel[i] * dt;
+                    body.vel += acc[i] * dt;
+                }
+            }
+            Integrator::SemiImplicitEuler => {
+                for (i, body) in bodies.iter_mut().enumerate() {
+                    body.vel += acc[i] * dt;
+                    body.pos += body.vel * dt;
+                }
+            }
+            Integrator::VelocityVerlet => {
+                let next_pos: Vec<_> = (0..bodies.len())
+                    .map(|i| pos[i] + vel[i] * dt + acc[i] * (0.5 * dt * dt))
+                    .collect();
+                let next_acc = gravity.accelerations(bodies, &next_pos);
+                for (i, body) in bodies.iter_mut().enumerate() {
+                    body.pos = next_pos[i];
+                    body.vel += (acc[i] + next_acc[i]) * (0.5 * dt);
+                }
+            }
+            Integrator::Rk4 => {
+                let offset = |base: &[Vector2<f32>], k: &[Vector2<f32>], h: f32| -> Vec<_> {
+                    base.iter().zip(k).map(|(b, k)| b + k * h).collect()
+                };
+
+                let k1_pos = vel.clone();
+                let k1_vel = acc.clone();
+                let k2_pos = offset(&vel, &k1_vel, 0.5 * dt);
+                let k2_vel = gravity.accelerations(bodies, &offset(&pos, &k1_pos, 0.5 * dt));
+                let k3_pos = offset(&vel, &k2_vel, 0.5 * dt);
+                let k3_vel = gravity.accelerations(bodies, &offset(&pos, &k2_pos, 0.5 * dt));
+                let k4_pos = offset(&vel, &k3_vel, dt);
+                let k4_vel = gravity.accelerations(bodies, &offset(&pos, &k3_pos, dt));
+
+                for (i, body) in bodies.iter_mut().enumerate() {
+                    body.pos +=
+                        (k1_pos[i] + k2_pos[i] * 2.0 + k3_pos[i] * 2.0 + k4_pos[i]) * (dt / 6.0);
+                    body.vel +=
+                        (k1_vel[i] + k2_vel[i] * 2.0 + k3_vel[i] * 2.0 + k4_vel[i]) * (dt / 6.0);
+                }
+            }
+        }
+        acc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ecolor::Color32;
+
+    use super::*;
+    use crate::gravity::circular_orbit_velocity;
+
+    #[test]
+    fn verlet_keeps_a_circular_orbit_bounded() {
+        let gravity = GravitySettings {
+            softening: 0.0,
+            ..GravitySettings::default()
+        };
+        let mut sun = CelestialBody::new(Vector2::zeros(), 1.0e6, Color32::YELLOW);
+        // Gravity switches off between overlapping bodies.
+        sun.radius = 5.0;
+        let mut planet = CelestialBody::new(Vector2::new(100.0, 0.0), 1.0, Color32::WHITE);
+        planet.vel = circular_orbit_velocity(
+            sun.mass + planet.mass,
+            sun.pos,
+            planet.pos,
+            gravity.gravity_constant,
+        );
+        let mut bodies = vec![sun, planet];
+
+        for step in 0..10_000 {
+            Integrator::VelocityVerlet.step(&mut bodies, 0.1, &gravity);
+            let r = (bodies[1].pos - bodies[0].pos).norm();
+            assert!((r - 100.0).abs() < 1.0, "radius {r} after {step} steps");
+        }
+    }
+}
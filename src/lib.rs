@@ -0,0 +1,22 @@
+//! The N-body engine behind the interplanetary simulation, usable without any UI.
+//!
+//! [`SimState`] holds the bodies and every setting that affects how they evolve;
+//! [`SimState::step`] advances them, and [`SimState::total_energy`] and
+//! [`SimState::barycenter`] report on the result. [`Scenario`] builds the same starting
+//! systems the app offers.
+
+mod barnes_hut;
+pub mod body;
+pub mod gravity;
+pub mod integrator;
+pub mod scenario;
+pub mod sim;
+mod spatial_hash;
+
+pub use body::{BodyShape, CelestialBody, RadiusMode};
+/// The color every body carries, re-exported so callers needn't depend on `ecolor`.
+pub use ecolor::Color32;
+pub use gravity::{G, GravitySettings};
+pub use integrator::Integrator;
+pub use scenario::{Scenario, ScenarioParams};
+pub use sim::{Boundary, CollisionMode, Fragmentation, SimState};
@@ -1,204 +1,4451 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
 use eframe::{self, App, Frame, egui};
 use egui::{Color32, Pos2, Rect};
 use nalgebra::Vector2;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+use web_time::Instant;
 
-const G: f32 = 6.67430e-5; // Gravitational constant
+mod cli;
 
-struct CelestialBody {
-    pos: Vector2<f32>,
-    vel: Vector2<f32>,
-    mass: f32,
+use cli::CliArgs;
+use egui_interplanetary_simulation::body::{DEFAULT_DENSITY, blend_colors};
+use egui_interplanetary_simulation::gravity::circular_orbit_velocity;
+use egui_interplanetary_simulation::scenario::{
+    EARTH_MASS, OrbitDirection, Scenario, ScenarioParams,
+};
+use egui_interplanetary_simulation::sim::{SimEventKind, remap_index};
+use egui_interplanetary_simulation::{
+    BodyShape, Boundary, CelestialBody, CollisionMode, Fragmentation, GravitySettings, Integrator,
+    RadiusMode, SimState,
+};
+
+const FIXED_DT: f32 = 1.0 / 120.0; // Physics timestep in seconds
+const MAX_STEPS_PER_FRAME: u32 = 8;
+/// Adaptive steps cover this fraction of the shortest time to close approach.
+const ADAPTIVE_DT_FRACTION: f32 = 0.05;
+/// Adaptive steps may grow by at most this factor from one step to the next.
+const ADAPTIVE_DT_GROWTH: f32 = 1.1;
+const DEFAULT_SEED: u64 = 42;
+/// Looks at the world origin, where every scenario is centered.
+const DEFAULT_CAMERA_POS: Pos2 = Pos2::ZERO;
+/// Keyboard pan speed in screen points per second.
+const KEY_PAN_SPEED: f32 = 400.0;
+/// Keyboard zoom rate, as a fraction of the current zoom per second.
+const KEY_ZOOM_RATE: f32 = 1.5;
+/// Fraction of its speed a coasting pan loses per second is `1 - e^-friction`.
+const DEFAULT_PAN_FRICTION: f32 = 4.0;
+/// A coasting pan slower than this, in screen points per second, comes to rest.
+const PAN_REST_SPEED: f32 = 5.0;
+/// How much of each frame's drag speed goes into the pan velocity; the rest is the
+/// previous estimate, so a single jittery frame doesn't decide the throw.
+const PAN_VELOCITY_SMOOTHING: f32 = 0.5;
+/// How far outside the view a body may sit and still get its arrows drawn.
+const ARROW_CULL_MARGIN: f32 = 50.0;
+/// The heaviest this many bodies pull on an orbit prediction; the rest are left out.
+const PREDICTION_ATTRACTORS: usize = 16;
+const ENERGY_HISTORY_LEN: usize = 2000;
+/// Seconds between recomputing the O(n²) energy, momentum and well-depth readouts.
+const DIAGNOSTICS_INTERVAL: f32 = 0.25;
+/// Frames averaged for the performance overlay.
+const PERF_WINDOW: usize = 60;
+/// Snapshots kept for undo; the oldest is dropped past this many.
+const UNDO_LIMIT: usize = 50;
+/// Smallest on-screen radius, in points, that still counts as a hit when picking.
+const MIN_PICK_RADIUS: f32 = 5.0;
+/// Frame All leaves this fraction of the view empty around the bodies.
+const FRAME_MARGIN: f32 = 0.1;
+/// Frame All never zooms in further than a box this wide, in world units, would need.
+const MIN_FRAME_SIZE: f32 = 200.0;
+/// Seconds a double-click camera flight takes.
+const CAMERA_FLIGHT_DURATION: f32 = 0.4;
+/// A double-clicked body ends up this fraction of the view's shorter side across.
+const FOCUS_VIEW_FRACTION: f32 = 0.1;
+
+/// Starting value of the speed clamp when it is switched on.
+const DEFAULT_MAX_SPEED: f32 = 1000.0;
+/// Starting cap when the body limit is switched on.
+const DEFAULT_MAX_BODIES: usize = 1000;
+/// Bodies at least this fraction of the heaviest mass are never recycled.
+const RECYCLE_MASS_FRACTION: f32 = 0.01;
+/// Starting drag coefficient when damping is switched on.
+const DEFAULT_DAMPING: f32 = 0.05;
+
+/// Label text height, in points, at zoom 1; it shrinks as the view zooms out.
+const LABEL_FONT_SIZE: f32 = 12.0;
+const DEFAULT_LABEL_MIN_MASS: f32 = 1000.0;
+/// Launch directions tried in the probe planner's coarse pass.
+const PLANNER_ANGLES: usize = 24;
+/// Launch speeds tried per direction in the coarse pass, spaced logarithmically.
+const PLANNER_SPEEDS: usize = 12;
+/// Rounds of local search refining the best launch from the coarse pass.
+const PLANNER_REFINEMENTS: usize = 40;
+/// A planned path counts as reaching the target within this many world units.
+const PLANNER_TOLERANCE: f32 = 10.0;
+/// Planned probes start this far outside the launching body's surface.
+const PROBE_CLEARANCE: f32 = 2.0;
+const PROBE_MASS: f32 = 1.0;
+/// Body pairs times steps the probe planner may integrate per frame before it waits for
+/// the next one.
+const PLANNER_FRAME_WORK: usize = 2_000_000;
+/// Tidal arrows are drawn this long, in points, whatever their strength.
+const TIDE_ARROW_LENGTH: f32 = 30.0;
+/// Off-screen arrows sit this far inside the edge of the view, in points.
+const OFFSCREEN_ARROW_INSET: f32 = 12.0;
+/// Length of the off-screen arrow for the heaviest body, in points.
+const OFFSCREEN_ARROW_SIZE: f32 = 14.0;
+
+/// Every input the app responds to, by section, as (input, what it does). The Help
+/// window lists exactly this, so a new binding belongs here as well as in its handler.
+const HELP: [(&str, &[(&str, &str)]); 3] = [
+    (
+        "Keyboard",
+        &[
+            ("W A S D / arrows", "Pan the view"),
+            ("+ / -", "Zoom in and out"),
+            ("F", "Frame all bodies"),
+            (
+                "Tab / Shift+Tab",
+                "Select and follow the next or previous body",
+            ),
+            (
+                "Delete / Backspace",
+                "Delete the box selection, or else the selected body",
+            ),
+            ("Ctrl+Z", "Undo"),
+            ("Ctrl+Shift+Z / Ctrl+Y", "Redo"),
+            ("Escape", "Clear the measurement"),
+            ("H / ?", "Show or hide this help"),
+        ],
+    ),
+    (
+        "Mouse",
+        &[
+            (
+                "Click",
+                "Select a body, or clear the selection on empty space",
+            ),
+            ("Drag a body", "Carry it; letting go throws it"),
+            ("Drag empty space", "Create a body, moving along the drag"),
+            ("Shift+click", "Spawn a ring of asteroids"),
+            ("Shift+drag", "Box-select bodies"),
+            ("Double-click", "Fly to a body, or frame everything"),
+            ("Right-click", "Body or background menu"),
+            (
+                "Right or middle drag",
+                "Pan the view; let go mid-drag to throw it",
+            ),
+            (
+                "Scroll / pinch",
+                "Zoom around the pointer; trackpads pan with two fingers",
+            ),
+            ("Click the minimap", "Move the view there"),
+        ],
+    ),
+    (
+        "Tools",
+        &[
+            (
+                "Measure",
+                "Click two points or drag to measure distance and angle",
+            ),
+            (
+                "Plan Probe",
+                "Click a target to launch a probe from the selected body",
+            ),
+            (
+                "Compare integrators",
+                "Branch the run and step it with a second integrator",
+            ),
+        ],
+    ),
+];
+
+/// Mean of the last [`PERF_WINDOW`] samples.
+#[derive(Default)]
+struct RollingAverage {
+    samples: VecDeque<f32>,
+    sum: f32,
+}
+
+impl RollingAverage {
+    fn push(&mut self, sample: f32) {
+        self.samples.push_back(sample);
+        self.sum += sample;
+        if self.samples.len() > PERF_WINDOW {
+            self.sum -= self.samples.pop_front().unwrap_or_default();
+        }
+    }
+
+    fn mean(&self) -> f32 {
+        if self.samples.is_empty() {
+            0.0
+        } else {
+            self.sum / self.samples.len() as f32
+        }
+    }
+}
+
+/// Where the Save and Load buttons read and write the scene.
+const SAVE_PATH: &str = "simulation.json";
+/// Where Export CSV writes the recorded trajectories.
+const CSV_PATH: &str = "trajectories.csv";
+/// Screenshots and dumped frames go here unless the user picks another directory.
+const DEFAULT_SCREENSHOT_DIR: &str = "screenshots";
+/// Recording stops by itself once it holds this many body samples.
+const MAX_RECORDED_ROWS: usize = 2_000_000;
+
+/// Fractional part of the golden ratio. Stepping the hue by it never lands two colors
+/// close together, however many are drawn.
+const GOLDEN_RATIO_FRACTION: f32 = 0.618_034;
+
+/// The `index`th color of a sequence of bright, saturated hues that stay well apart from
+/// each other and always show up against the black background.
+fn distinct_color(index: u32) -> Color32 {
+    let hue = (index as f32 * GOLDEN_RATIO_FRACTION).fract();
+    egui::ecolor::Hsva::new(hue, 0.65, 1.0, 1.0).into()
+}
+
+/// Moves `color` towards the gray of the same brightness by `t` in `0.0..=1.0`.
+fn desaturate(color: Color32, t: f32) -> Color32 {
+    let gray = (0.299 * color.r() as f32 + 0.587 * color.g() as f32 + 0.114 * color.b() as f32)
+        .round() as u8;
+    blend_colors(color, Color32::from_gray(gray), t.clamp(0.0, 1.0))
+}
+
+/// Samples a viridis-like colormap at `t` in `0.0..=1.0`.
+fn viridis(t: f32) -> Color32 {
+    const STOPS: [Color32; 5] = [
+        Color32::from_rgb(68, 1, 84),
+        Color32::from_rgb(59, 82, 139),
+        Color32::from_rgb(33, 145, 140),
+        Color32::from_rgb(94, 201, 98),
+        Color32::from_rgb(253, 231, 37),
+    ];
+    let scaled = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let k = (scaled as usize).min(STOPS.len() - 2);
+    blend_colors(STOPS[k], STOPS[k + 1], scaled - k as f32)
+}
+
+/// Concentric circles drawn for one body's glow.
+const GLOW_LAYERS: usize = 6;
+/// At full strength the outermost glow circle is this many body radii out.
+const GLOW_REACH: f32 = 4.0;
+/// Opacity of the innermost glow circle at full strength.
+const GLOW_ALPHA: f32 = 0.25;
+
+/// Paints a halo of fading translucent circles around a body, wider and brighter as
+/// `strength` goes from 0 to 1. Meant to go underneath the body's own circle.
+fn draw_glow(painter: &egui::Painter, center: Pos2, radius: f32, color: Color32, strength: f32) {
+    if strength <= 0.01 {
+        return;
+    }
+    // Outermost first, so the brighter inner layers stack on top.
+    for layer in (1..=GLOW_LAYERS).rev() {
+        let t = layer as f32 / GLOW_LAYERS as f32;
+        let fade = (GLOW_LAYERS + 1 - layer) as f32 / GLOW_LAYERS as f32;
+        let alpha = GLOW_ALPHA * strength * fade;
+        painter.circle_filled(
+            center,
+            radius * (1.0 + GLOW_REACH * strength * t),
+            color.gamma_multiply(alpha),
+        );
+    }
+}
+
+/// Width of a [`BodyShape::Ring`] outline, as a fraction of the radius.
+const RING_WIDTH: f32 = 0.35;
+/// Rays on a [`BodyShape::Star`].
+const STAR_RAYS: usize = 8;
+
+/// A filled core with rays reaching out to `radius`, the glyph for [`BodyShape::Star`].
+fn draw_star(painter: &egui::Painter, center: Pos2, radius: f32, color: Color32) {
+    let core = radius * 0.55;
+    let mut mesh = egui::Mesh::default();
+    for k in 0..STAR_RAYS {
+        let angle = k as f32 * std::f32::consts::TAU / STAR_RAYS as f32;
+        let dir = egui::Vec2::angled(angle);
+        // Each ray is a thin triangle from the core's rim out to a point at the radius.
+        let base = center + dir * core * 0.8;
+        let side = dir.rot90() * core * 0.35;
+        let first = mesh.vertices.len() as u32;
+        mesh.colored_vertex(base + side, color);
+        mesh.colored_vertex(base - side, color);
+        mesh.colored_vertex(center + dir * radius, color);
+        mesh.add_triangle(first, first + 1, first + 2);
+    }
+    painter.add(mesh);
+    painter.circle_filled(center, core, color);
+}
+
+/// Merge ripples alive at once; the oldest is dropped past this many.
+const MAX_RIPPLES: usize = 32;
+/// Seconds a merge ripple takes to spread out and fade.
+const RIPPLE_LIFETIME: f32 = 1.0;
+/// How far a merge ripple spreads beyond the merged body, in points.
+const RIPPLE_REACH: f32 = 80.0;
+
+/// Expanding ring drawn where two bodies merged. Cosmetic, and timed by the wall clock so
+/// it plays out the same at any time scale or while paused.
+struct Ripple {
+    origin: Vector2<f32>,
+    started: Instant,
+    /// Radius of the merged body, in world units; the ring starts from its edge.
     radius: f32,
-    color: Color32,
 }
 
-impl CelestialBody {
-    fn new(pos: Vector2<f32>, mass: f32, color: Color32) -> Self {
+/// Whole-system quantities too costly to recompute every frame, with when they were taken.
+struct Diagnostics {
+    energy: (f32, f32, f32),
+    angular_momentum: f32,
+    /// Empty unless the well tint is shown.
+    well_depths: Vec<f32>,
+    taken: Instant,
+}
+
+/// Predicted path of the selected body, kept until anything it was integrated from changes.
+struct Prediction {
+    /// The subject first, then its attractors, as they were at the start of `path`.
+    bodies: Vec<CelestialBody>,
+    dt: f32,
+    integrator: Integrator,
+    gravity: GravitySettings,
+    path: Vec<Vector2<f32>>,
+}
+
+/// Probe launch search spread over frames so it never stalls one. A coarse grid of
+/// launch directions and speeds picks the best start, then a compass search refines it,
+/// halving its steps whenever no neighbor improves.
+struct ProbePlan {
+    /// The launching body and everything pulling on the probe, as they were when the
+    /// search began. The probe goes out from wherever the body with `origin.id` is by then.
+    origin: CelestialBody,
+    attractors: Vec<CelestialBody>,
+    target: Vector2<f32>,
+    min_log_speed: f32,
+    angle_step: f32,
+    speed_step: f32,
+    /// Miss distance, angle and log speed of the best launch so far.
+    best: (f32, f32, f32),
+    /// Coarse grid cells tried so far.
+    cells_done: usize,
+    refinements_done: usize,
+    /// The simulation was running when the search began, and runs again once it ends.
+    resume: bool,
+}
+
+impl ProbePlan {
+    const CELLS: usize = PLANNER_ANGLES * PLANNER_SPEEDS;
+
+    fn finished(&self) -> bool {
+        self.cells_done == Self::CELLS
+            && (self.refinements_done == PLANNER_REFINEMENTS || self.best.0 <= PLANNER_TOLERANCE)
+    }
+
+    /// Share of the worst-case search done so far, from 0 to 1.
+    fn progress(&self) -> f32 {
+        (self.cells_done + self.refinements_done) as f32
+            / (Self::CELLS + PLANNER_REFINEMENTS) as f32
+    }
+}
+
+/// A probe leaving `origin` in the direction `angle` at `log_speed.exp()` relative to it.
+fn launch_probe(origin: &CelestialBody, angle: f32, log_speed: f32) -> CelestialBody {
+    let dir = Vector2::new(angle.cos(), angle.sin());
+    let pos = origin.pos + dir * (origin.radius + PROBE_CLEARANCE);
+    let mut probe = CelestialBody::new(pos, PROBE_MASS, Color32::WHITE);
+    probe.vel = origin.vel + dir * log_speed.exp();
+    probe
+}
+
+/// Camera glide started by a double-click, timed by the wall clock like [`Ripple`].
+struct CameraFlight {
+    /// Body to end up on, tracked as it moves; `None` heads for `to` instead.
+    body: Option<usize>,
+    to: Vector2<f32>,
+    from: Pos2,
+    from_zoom: f32,
+    to_zoom: f32,
+    started: Instant,
+}
+
+/// Seed for the background stars, fixed so they don't reshuffle between runs.
+const STARFIELD_SEED: u64 = 7;
+/// Stars scroll this fraction as fast as the world does, so they read as far away.
+const STARFIELD_PARALLAX: f32 = 0.05;
+/// Background nebulae as screen-space center (fraction of the view), radius (fraction
+/// of the view's longer side) and core color.
+const NEBULAE: [(egui::Vec2, f32, Color32); 2] = [
+    (
+        egui::vec2(0.3, 0.35),
+        0.45,
+        Color32::from_rgba_premultiplied(30, 10, 45, 45),
+    ),
+    (
+        egui::vec2(0.75, 0.7),
+        0.35,
+        Color32::from_rgba_premultiplied(8, 25, 40, 40),
+    ),
+];
+
+/// Writes `image` to `path` as a PNG, creating the directory if needed.
+fn save_png(path: &std::path::Path, image: &egui::ColorImage) -> image::ImageResult<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let rgba: Vec<u8> = image.pixels.iter().flat_map(|c| c.to_array()).collect();
+    let [width, height] = image.size;
+    image::save_buffer(
+        path,
+        &rgba,
+        width as u32,
+        height as u32,
+        image::ExtendedColorType::Rgba8,
+    )
+}
+
+/// Small line plot of `history`, oldest on the left, with samples `x_step` apart along an
+/// x axis labelled `x_label`.
+fn history_plot(ui: &mut egui::Ui, id: &str, history: &VecDeque<f32>, x_step: f64, x_label: &str) {
+    let points: egui_plot::PlotPoints = history
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| [i as f64 * x_step, v as f64])
+        .collect();
+    egui_plot::Plot::new(id)
+        .height(120.0)
+        .width(200.0)
+        .x_axis_label(x_label)
+        .show(ui, |plot_ui| plot_ui.line(egui_plot::Line::new(points)));
+}
+
+/// Paints each of [`NEBULAE`] as a fan fading from its core color to transparent.
+fn draw_nebulae(painter: &egui::Painter, rect: Rect) {
+    const SEGMENTS: u32 = 48;
+    let mut mesh = egui::Mesh::default();
+    for (center, radius, color) in NEBULAE {
+        let center = rect.min + center * rect.size();
+        let radius = radius * rect.width().max(rect.height());
+        let base = mesh.vertices.len() as u32;
+        mesh.colored_vertex(center, color);
+        for k in 0..SEGMENTS {
+            let angle = k as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+            let rim = center + egui::vec2(angle.cos(), angle.sin()) * radius;
+            mesh.colored_vertex(rim, Color32::TRANSPARENT);
+            mesh.add_triangle(base, base + 1 + k, base + 1 + (k + 1) % SEGMENTS);
+        }
+    }
+    painter.add(mesh);
+}
+
+/// Minor grid lines are never drawn closer together than this many points.
+const MIN_GRID_SPACING: f32 = 12.0;
+
+const MINIMAP_SIZE: egui::Vec2 = egui::vec2(220.0, 160.0);
+
+/// Bottom-right corner of the main view where the minimap is drawn.
+fn minimap_rect(view: Rect) -> Rect {
+    Rect::from_min_size(
+        view.right_bottom() - MINIMAP_SIZE - egui::vec2(10.0, 10.0),
+        MINIMAP_SIZE,
+    )
+}
+
+/// Fit-to-content mapping between world space and the minimap rect.
+struct MinimapTransform {
+    rect: Rect,
+    world_center: Vector2<f32>,
+    scale: f32,
+}
+
+impl MinimapTransform {
+    fn fit(rect: Rect, min: Vector2<f32>, max: Vector2<f32>) -> Self {
+        let extent = (max - min).sup(&Vector2::repeat(1.0));
+        let scale = (rect.width() / extent.x).min(rect.height() / extent.y) * 0.9;
         Self {
-            pos,
-            vel: Vector2::zeros(),
-            mass,
-            radius: (mass / std::f32::consts::PI).sqrt() / 2.0,
-            color,
+            rect,
+            world_center: (min + max) / 2.0,
+            scale,
         }
     }
 
-    // fn apply_gravity(&mut self, other: &CelestialBody) {
-    //     let dir = other.pos - self.pos;
-    //     let dist_sq = dir.norm_squared();
-    //     if dist_sq > (self.radius + other.radius).powi(2) {
-    //         let force_mag = G * self.mass * other.mass / dist_sq;
-    //         let force = dir.normalize() * force_mag;
-    //         self.vel += force / self.mass;
-    //     }
-    // }
+    fn to_screen(&self, world: Vector2<f32>) -> Pos2 {
+        let v = (world - self.world_center) * self.scale;
+        self.rect.center() + egui::vec2(v.x, v.y)
+    }
+
+    fn to_world(&self, screen: Pos2) -> Vector2<f32> {
+        let v = (screen - self.rect.center()) / self.scale;
+        self.world_center + Vector2::new(v.x, v.y)
+    }
+}
 
-    fn apply_gravity(&mut self, other: &CelestialBody) {
-        let dir = other.pos - self.pos;
-        let dist_sq = dir.norm_squared();
-        let dist = dist_sq.sqrt();
+/// Outline drawn around the whole system to show how spread out it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum ExtentOverlay {
+    Off,
+    /// Axis-aligned box around every body.
+    Box,
+    /// Circle around the barycenter reaching the farthest body.
+    Circle,
+}
 
-        if dist_sq > (self.radius + other.radius).powi(2) {
-            let force_mag = G * self.mass * other.mass / dist_sq;
-            let force = dir.normalize() * force_mag;
-            self.vel += force / self.mass;
+impl ExtentOverlay {
+    const ALL: [ExtentOverlay; 3] = [
+        ExtentOverlay::Off,
+        ExtentOverlay::Box,
+        ExtentOverlay::Circle,
+    ];
 
-            // If close to a big mass, give extra tangential velocity to "orbit"
-            if dist < 150.0 && other.mass > self.mass * 5.0 {
-                let tangential = Vector2::new(-dir.y, dir.x).normalize();
-                self.vel += tangential * 0.05;
-            }
+    fn label(self) -> &'static str {
+        match self {
+            ExtentOverlay::Off => "Off",
+            ExtentOverlay::Box => "Bounding box",
+            ExtentOverlay::Circle => "Bounding circle",
         }
     }
-    fn update(&mut self, dt: f32) {
-        self.pos += self.vel * dt;
+}
+
+/// What the view is drawn relative to. Only rendering changes; the physics always runs
+/// in world coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum ReferenceFrame {
+    World,
+    /// Moves with the center of mass.
+    Barycenter,
+    /// Moves with the selected body.
+    Body,
+}
+
+impl ReferenceFrame {
+    const ALL: [ReferenceFrame; 3] = [
+        ReferenceFrame::World,
+        ReferenceFrame::Barycenter,
+        ReferenceFrame::Body,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ReferenceFrame::World => "World",
+            ReferenceFrame::Barycenter => "Barycenter",
+            ReferenceFrame::Body => "Follow Body",
+        }
     }
 }
 
-struct InterplanetarySimulation {
+/// The part of the simulation written by Save and restored by Load.
+#[derive(Serialize, Deserialize)]
+struct SavedScene {
     bodies: Vec<CelestialBody>,
     camera_pos: Pos2,
     zoom: f32,
 }
 
-impl Default for InterplanetarySimulation {
+/// A copy of the simulation branched off at one moment and stepped alongside it with
+/// another integrator, to watch the two drift apart.
+struct Comparison {
+    sim: SimState,
+    /// Index of the tracked body in the main simulation and in the branch. They differ
+    /// once the two see different collisions.
+    tracked: (Option<usize>, Option<usize>),
+    /// Distance between the two copies of the tracked body after each step.
+    divergence: VecDeque<f32>,
+}
+
+/// Two-body orbit of a body around its dominant attractor, from its current state.
+struct OrbitalElements {
+    attractor: usize,
+    /// Distance to the attractor.
+    radius: f32,
+    /// Speed relative to the attractor.
+    speed: f32,
+    /// Orbital energy per unit mass; negative while bound.
+    specific_energy: f32,
+    /// From vis-viva; `None` once the body is unbound.
+    semi_major_axis: Option<f32>,
+    /// Magnitude of the eccentricity vector: 0 circular, below 1 elliptic, 1 and up open.
+    eccentricity: f32,
+}
+
+/// One body's state at one recorded step.
+struct TrajectorySample {
+    step: u64,
+    time: f32,
+    body: u64,
+    pos: Vector2<f32>,
+    vel: Vector2<f32>,
+}
+
+/// Preferences remembered across runs through eframe's storage. Bodies are not saved;
+/// the scenario is rebuilt from `scenario` and `seed` instead.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    scenario: Scenario,
+    seed: u64,
+    scenario_params: ScenarioParams,
+    camera_pos: Pos2,
+    zoom: f32,
+    pan_inertia: bool,
+    pan_friction: f32,
+    integrator: Integrator,
+    collision_mode: CollisionMode,
+    restitution: f32,
+    friction: f32,
+    fragmentation: Fragmentation,
+    boundary: Boundary,
+    boundary_half_extent: Vector2<f32>,
+    gravity: GravitySettings,
+    radius_mode: RadiusMode,
+    radius_scale: f32,
+    render_scale: f32,
+    show_trails: bool,
+    trail_length: usize,
+    show_minimap: bool,
+    show_grid: bool,
+    show_velocity: bool,
+    show_prediction: bool,
+    show_labels: bool,
+    show_offscreen: bool,
+    show_glow: bool,
+    show_ripples: bool,
+    glow_intensity: f32,
+    show_starfield: bool,
+    star_density: f32,
+    show_nebula: bool,
+    show_well_tint: bool,
+    well_tint_strength: f32,
+    show_potential: bool,
+    show_soi: bool,
+    show_barycenter: bool,
+    reference_frame: ReferenceFrame,
+    extent_overlay: ExtentOverlay,
+    show_perf: bool,
+    color_by_speed: bool,
+    show_energy_plot: bool,
+    show_momentum_plot: bool,
+    time_scale: f32,
+    substeps: u32,
+}
+
+impl Default for Settings {
     fn default() -> Self {
-        let mut bodies = Vec::new();
-        // Sun
-        bodies.push(CelestialBody::new(
-            Vector2::new(400.0, 300.0),
-            10000.0,
-            Color32::YELLOW,
-        ));
-        // Earth
-        bodies.push(CelestialBody::new(
-            Vector2::new(500.0, 300.0),
-            100.0,
-            Color32::from_rgb(0, 128, 255),
-        ));
-        bodies[1].vel.y = 80.0;
+        InterplanetarySimulation::default().settings()
+    }
+}
 
-        let mut bodies = Vec::new();
-        let mut rng = rand::rng();
-        // Asteroids
-        for _ in 0..200 {
-            let angle = rng.random_range(0.0..std::f32::consts::TAU);
-            let distance = rng.random_range(150.0..350.0);
-            let pos = Vector2::new(
-                400.0 + distance * angle.cos(),
-                300.0 + distance * angle.sin(),
-            );
-            let mass = rng.random_range(1.0..5.0);
-            let color = Color32::GRAY;
-            let mut asteroid = CelestialBody::new(pos, mass, color);
+struct InterplanetarySimulation {
+    sim: SimState,
+    /// Scene that Reset rebuilds.
+    scenario: Scenario,
+    /// Seed for every random choice made while building the scenario.
+    seed: u64,
+    scenario_params: ScenarioParams,
+    camera_pos: Pos2,
+    zoom: f32,
+    /// Keep panning after a drag is released, slowing down by `pan_friction`.
+    pan_inertia: bool,
+    pan_friction: f32,
+    /// Speed of the last camera drag, or of the coast after it, in screen points per second.
+    pan_velocity: egui::Vec2,
+    camera_flight: Option<CameraFlight>,
+    selected: Option<usize>,
+    /// Bodies picked with a Shift+drag box, for the group operations.
+    group: HashSet<usize>,
+    /// Screen position where a Shift+drag selection box began.
+    box_select_start: Option<Pos2>,
+    /// Body grabbed with the pointer. It is pinned to `held_target` after every step,
+    /// so gravity never gets to move it while held.
+    held: Option<usize>,
+    held_target: Vector2<f32>,
+    /// World velocity of the pointer over the last frame, given to the held body on release.
+    throw_velocity: Vector2<f32>,
+    /// Clicks and drags measure distances instead of selecting or creating bodies.
+    measure_mode: bool,
+    /// The next click picks a target for a probe launched from the selected body.
+    planning_probe: bool,
+    probe_plan: Option<ProbePlan>,
+    /// World endpoints of the ruler line.
+    measure: Option<(Vector2<f32>, Vector2<f32>)>,
+    /// The ruler's end still follows the pointer.
+    measure_live: bool,
+    /// Velocity the Selection window's "Add Velocity" gives every grouped body.
+    group_velocity_offset: Vector2<f32>,
+    /// Body the camera is locked onto; cleared when the body disappears or the user pans.
+    follow: Option<usize>,
+    /// Keep the center of mass in the middle of the view.
+    lock_barycenter: bool,
+    show_barycenter: bool,
+    reference_frame: ReferenceFrame,
+    extent_overlay: ExtentOverlay,
+    /// Frame origin after each step that recorded trails, lined up with the newest end of
+    /// every trail so trails can be drawn relative to the frame.
+    frame_history: VecDeque<Vector2<f32>>,
+    /// Frame and frame body that `frame_history` and `frame_origin` were taken from.
+    frame_source: (ReferenceFrame, Option<usize>),
+    /// Frame origin at the last rendered frame; the camera moves along with it.
+    frame_origin: Option<Vector2<f32>>,
+    /// World position where a drag-to-create gesture began.
+    create_drag_start: Option<Vector2<f32>>,
+    /// Body under the pointer at the last right-click; `None` opens the background menu.
+    context_target: Option<usize>,
+    /// World position of the last right-click.
+    context_point: Vector2<f32>,
+    /// Body stored by Copy in the context menu, pasted at the pointer by Paste.
+    clipboard: Option<CelestialBody>,
+    /// Colors handed out so far; the next body gets [`distinct_color`] of this.
+    colors_assigned: u32,
+    /// Mass of the next body from Add Planet or drag-to-create.
+    new_body_mass: f32,
+    drag_velocity_scale: f32,
+    /// Adding bodies past this many is refused, or recycles old ones; `None` is unlimited.
+    max_bodies: Option<usize>,
+    /// At the cap, remove the oldest disposable body instead of refusing the new one.
+    recycle_oldest: bool,
+    /// Asteroids per Shift+click ring.
+    ring_count: usize,
+    /// Inner and outer radius of a spawned ring.
+    ring_radii: (f32, f32),
+    /// Mass range of a ring's asteroids.
+    ring_mass: (f32, f32),
+    /// Multiplies drawn radii only; collisions keep using the physical radius.
+    radius_scale: f32,
+    /// Multiplies every on-screen size: radii, line widths, arrows and overlay text. All
+    /// of these are in egui points, which already follow the display's pixels per point,
+    /// so this is only for taste or unusually dense screens.
+    render_scale: f32,
+    /// Bodies smaller than this many physical pixels across their radius on screen are
+    /// drawn as batched squares rather than circles.
+    lod_radius: f32,
+    /// Number of physics steps to look ahead for the selected body; 0 disables it.
+    prediction_steps: usize,
+    /// Draw the selected body's predicted path.
+    show_prediction: bool,
+    prediction: Option<Prediction>,
+    /// Show the tidal stretch across the selected body from its dominant attractor.
+    show_tides: bool,
+    /// Draw bodies with a colormap of their speed instead of their own color.
+    color_by_speed: bool,
+    auto_speed_range: bool,
+    /// Speeds mapped to the two ends of the colormap.
+    speed_range: (f32, f32),
+    show_minimap: bool,
+    show_grid: bool,
+    show_velocity: bool,
+    /// World units of arrow length per unit of speed.
+    velocity_arrow_scale: f32,
+    /// Draw each body's net gravitational acceleration from the last step.
+    show_acceleration: bool,
+    /// World units of arrow length per unit of acceleration.
+    acceleration_arrow_scale: f32,
+    show_labels: bool,
+    /// Point at bodies outside the view from the edge of the view.
+    show_offscreen: bool,
+    /// Only bodies at least this heavy get an off-screen arrow; `None` points at all.
+    offscreen_min_mass: Option<f32>,
+    /// Draw a halo around each body, brighter for heavier ones.
+    show_glow: bool,
+    /// Send a fading ring out from every merge.
+    show_ripples: bool,
+    /// Newest last, at most [`MAX_RIPPLES`].
+    ripples: VecDeque<Ripple>,
+    /// Scales every halo; at 1.0 the heaviest body gets the full-size glow.
+    glow_intensity: f32,
+    show_starfield: bool,
+    /// Background stars per 10,000 square points of view.
+    star_density: f32,
+    /// Soft colored clouds behind the stars.
+    show_nebula: bool,
+    /// Wash out bodies sitting deep in a gravity well, a loose picture of their clocks
+    /// running slow. Drawing only; the physics never sees it.
+    show_well_tint: bool,
+    /// How far the deepest body is washed out towards gray, from 0 to 1.
+    well_tint_strength: f32,
+    /// Shade the background by gravitational potential.
+    show_potential: bool,
+    /// Side of one potential heatmap cell, in screen points.
+    potential_cell_size: f32,
+    /// Circle each body's sphere of influence relative to the heaviest body.
+    show_soi: bool,
+    /// Only bodies at least this heavy get a label; `None` labels everything.
+    label_min_mass: Option<f32>,
+    show_energy_plot: bool,
+    /// Total energy sampled every [`DIAGNOSTICS_INTERVAL`] of wall-clock time while running,
+    /// not once per frame or step, so the O(n²) sum stays off the frame budget.
+    energy_history: VecDeque<f32>,
+    show_momentum_plot: bool,
+    /// Total angular momentum, sampled alongside `energy_history`.
+    momentum_history: VecDeque<f32>,
+    diagnostics: Option<Diagnostics>,
+    paused: bool,
+    /// Set by the Single-Step button; consumed by the next physics pass.
+    step_once: bool,
+    /// Step with negative `dt`, running the motion backward.
+    reversed: bool,
+    time_scale: f32,
+    /// Every step is split into this many equal sub-steps, each a full `SimState::step`.
+    substeps: u32,
+    fixed_dt: f32,
+    /// Shrink the step during close encounters instead of always using `fixed_dt`.
+    adaptive_dt: bool,
+    /// Bounds on the adaptive step, in the same real-time units as `fixed_dt`.
+    dt_floor: f32,
+    dt_ceiling: f32,
+    /// Length of the most recent step; equals `fixed_dt` unless stepping adaptively.
+    step_dt: f32,
+    /// Real time not yet consumed by physics steps.
+    accumulator: f32,
+    /// Branch stepped with `comparison_integrator`, drawn as outlines over the main bodies.
+    comparison: Option<Comparison>,
+    comparison_integrator: Integrator,
+    /// Outcome of the last file or body-list action, shown in the Controls window.
+    file_status: Option<String>,
+    /// Directory screenshots and dumped frames are written to.
+    screenshot_dir: String,
+    /// Screenshots taken so far, numbering the next one.
+    screenshot_count: u64,
+    /// Save every rendered frame as a numbered PNG, for assembling into a GIF or video.
+    frame_dump: bool,
+    /// Number of the next dumped frame.
+    frame_index: u64,
+    /// Append every body's state to `recording` after each step.
+    recording_enabled: bool,
+    recording: Vec<TrajectorySample>,
+    /// Body lists from before each user edit, newest last.
+    undo_stack: VecDeque<Vec<CelestialBody>>,
+    redo_stack: Vec<Vec<CelestialBody>>,
+    /// An info-panel drag or text edit is under way and already has its snapshot.
+    edit_in_progress: bool,
+    /// Velocities set aside by Freeze Velocities, keyed by body id.
+    frozen_velocities: Option<HashMap<u64, Vector2<f32>>>,
+    show_perf: bool,
+    show_help: bool,
+    /// Seconds between frames.
+    frame_time: RollingAverage,
+    /// Seconds per frame spent in physics steps.
+    physics_time: RollingAverage,
+    /// Seconds per frame spent in `update` outside of physics.
+    ui_time: RollingAverage,
+}
+
+impl Default for InterplanetarySimulation {
+    fn default() -> Self {
+        Self::from_scenario(
+            Scenario::AsteroidBelt,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        )
+    }
+}
 
-            let to_center = Vector2::new(400.0, 300.0) - pos;
-            let tangential = Vector2::new(-to_center.y, to_center.x).normalize();
-            asteroid.vel = tangential * rng.random_range(10.0..30.0);
+impl InterplanetarySimulation {
+    /// Restores the settings saved by the previous run, if any, then applies the
+    /// command-line overrides on top.
+    fn new(cc: &eframe::CreationContext<'_>, cli: &CliArgs) -> Self {
+        let mut settings: Settings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+        settings.scenario = cli.scenario.unwrap_or(settings.scenario);
+        settings.seed = cli.seed.unwrap_or(settings.seed);
+        let mut app =
+            Self::from_scenario(settings.scenario, settings.seed, settings.scenario_params);
+        app.apply_settings(settings);
+        if let Some(path) = &cli.scene {
+            app.file_status = Some(match app.load_scene(path) {
+                Ok(()) => format!("Loaded {path}"),
+                Err(err) => format!("Load failed: {err}"),
+            });
+        }
+        app
+    }
 
-            bodies.push(asteroid);
+    fn settings(&self) -> Settings {
+        Settings {
+            scenario: self.scenario,
+            seed: self.seed,
+            scenario_params: self.scenario_params,
+            camera_pos: self.camera_pos,
+            zoom: self.zoom,
+            pan_inertia: self.pan_inertia,
+            pan_friction: self.pan_friction,
+            integrator: self.sim.integrator,
+            collision_mode: self.sim.collision_mode,
+            restitution: self.sim.restitution,
+            friction: self.sim.friction,
+            fragmentation: self.sim.fragmentation,
+            boundary: self.sim.boundary,
+            boundary_half_extent: self.sim.boundary_half_extent,
+            gravity: self.sim.gravity,
+            radius_mode: self.sim.radius_mode,
+            radius_scale: self.radius_scale,
+            render_scale: self.render_scale,
+            show_trails: self.sim.record_trails,
+            trail_length: self.sim.trail_length,
+            show_minimap: self.show_minimap,
+            show_grid: self.show_grid,
+            show_velocity: self.show_velocity,
+            show_prediction: self.show_prediction,
+            show_labels: self.show_labels,
+            show_offscreen: self.show_offscreen,
+            show_glow: self.show_glow,
+            show_ripples: self.show_ripples,
+            glow_intensity: self.glow_intensity,
+            show_starfield: self.show_starfield,
+            star_density: self.star_density,
+            show_nebula: self.show_nebula,
+            show_well_tint: self.show_well_tint,
+            well_tint_strength: self.well_tint_strength,
+            show_potential: self.show_potential,
+            show_soi: self.show_soi,
+            show_perf: self.show_perf,
+            show_barycenter: self.show_barycenter,
+            reference_frame: self.reference_frame,
+            extent_overlay: self.extent_overlay,
+            color_by_speed: self.color_by_speed,
+            show_energy_plot: self.show_energy_plot,
+            show_momentum_plot: self.show_momentum_plot,
+            time_scale: self.time_scale,
+            substeps: self.substeps,
         }
+    }
+
+    fn apply_settings(&mut self, settings: Settings) {
+        self.camera_pos = settings.camera_pos;
+        self.pan_inertia = settings.pan_inertia;
+        self.pan_friction = settings.pan_friction;
+        self.zoom = settings.zoom;
+        self.sim.integrator = settings.integrator;
+        self.sim.collision_mode = settings.collision_mode;
+        self.sim.restitution = settings.restitution;
+        self.sim.friction = settings.friction;
+        self.sim.fragmentation = settings.fragmentation;
+        self.sim.boundary = settings.boundary;
+        self.sim.boundary_half_extent = settings.boundary_half_extent;
+        self.sim.gravity = settings.gravity;
+        self.sim.set_radius_mode(settings.radius_mode);
+        self.radius_scale = settings.radius_scale;
+        self.render_scale = settings.render_scale;
+        self.sim.record_trails = settings.show_trails;
+        self.sim.trail_length = settings.trail_length;
+        self.show_minimap = settings.show_minimap;
+        self.show_grid = settings.show_grid;
+        self.show_velocity = settings.show_velocity;
+        self.show_prediction = settings.show_prediction;
+        self.show_labels = settings.show_labels;
+        self.show_offscreen = settings.show_offscreen;
+        self.show_glow = settings.show_glow;
+        self.show_ripples = settings.show_ripples;
+        self.glow_intensity = settings.glow_intensity;
+        self.show_starfield = settings.show_starfield;
+        self.star_density = settings.star_density;
+        self.show_nebula = settings.show_nebula;
+        self.show_well_tint = settings.show_well_tint;
+        self.well_tint_strength = settings.well_tint_strength;
+        self.show_potential = settings.show_potential;
+        self.show_soi = settings.show_soi;
+        self.show_perf = settings.show_perf;
+        self.show_barycenter = settings.show_barycenter;
+        self.reference_frame = settings.reference_frame;
+        self.extent_overlay = settings.extent_overlay;
+        self.color_by_speed = settings.color_by_speed;
+        self.show_energy_plot = settings.show_energy_plot;
+        self.show_momentum_plot = settings.show_momentum_plot;
+        self.time_scale = settings.time_scale;
+        self.substeps = settings.substeps.max(1);
+    }
 
+    fn from_scenario(scenario: Scenario, seed: u64, scenario_params: ScenarioParams) -> Self {
         Self {
-            bodies,
-            camera_pos: Pos2::new(400.0, 300.0),
+            sim: SimState::new(scenario.bodies(seed, &scenario_params)),
+            scenario,
+            seed,
+            scenario_params,
+            camera_pos: DEFAULT_CAMERA_POS,
             zoom: 1.0,
+            pan_inertia: true,
+            pan_friction: DEFAULT_PAN_FRICTION,
+            pan_velocity: egui::Vec2::ZERO,
+            camera_flight: None,
+            selected: None,
+            group: HashSet::new(),
+            box_select_start: None,
+            group_velocity_offset: Vector2::zeros(),
+            measure_mode: false,
+            planning_probe: false,
+            probe_plan: None,
+            measure: None,
+            measure_live: false,
+            held: None,
+            held_target: Vector2::zeros(),
+            throw_velocity: Vector2::zeros(),
+            follow: None,
+            lock_barycenter: false,
+            show_barycenter: false,
+            reference_frame: ReferenceFrame::World,
+            extent_overlay: ExtentOverlay::Off,
+            frame_history: VecDeque::new(),
+            frame_source: (ReferenceFrame::World, None),
+            frame_origin: None,
+            create_drag_start: None,
+            context_target: None,
+            context_point: Vector2::zeros(),
+            clipboard: None,
+            colors_assigned: 0,
+            new_body_mass: 2000.0,
+            drag_velocity_scale: 0.5,
+            max_bodies: None,
+            recycle_oldest: true,
+            ring_count: 100,
+            ring_radii: (150.0, 350.0),
+            ring_mass: (1.0, 5.0),
+            radius_scale: 1.0,
+            render_scale: 1.0,
+            lod_radius: 1.5,
+            prediction_steps: 600,
+            show_prediction: true,
+            prediction: None,
+            show_tides: false,
+            color_by_speed: false,
+            auto_speed_range: true,
+            speed_range: (0.0, 100.0),
+            show_minimap: true,
+            show_grid: false,
+            show_velocity: false,
+            show_labels: false,
+            show_offscreen: false,
+            offscreen_min_mass: Some(DEFAULT_LABEL_MIN_MASS),
+            show_glow: false,
+            show_ripples: true,
+            ripples: VecDeque::new(),
+            glow_intensity: 1.0,
+            show_starfield: true,
+            star_density: 2.0,
+            show_nebula: false,
+            show_well_tint: false,
+            well_tint_strength: 0.7,
+            show_potential: false,
+            potential_cell_size: 16.0,
+            show_soi: false,
+            label_min_mass: None,
+            velocity_arrow_scale: 1.0,
+            show_acceleration: false,
+            acceleration_arrow_scale: 1.0,
+            show_energy_plot: false,
+            energy_history: VecDeque::new(),
+            diagnostics: None,
+            show_momentum_plot: false,
+            momentum_history: VecDeque::new(),
+            paused: false,
+            reversed: false,
+            step_once: false,
+            time_scale: 1.0,
+            substeps: 1,
+            fixed_dt: FIXED_DT,
+            adaptive_dt: false,
+            dt_floor: FIXED_DT / 32.0,
+            dt_ceiling: FIXED_DT * 2.0,
+            step_dt: FIXED_DT,
+            accumulator: 0.0,
+            comparison: None,
+            comparison_integrator: Integrator::Rk4,
+            file_status: None,
+            screenshot_dir: DEFAULT_SCREENSHOT_DIR.to_owned(),
+            screenshot_count: 0,
+            frame_dump: false,
+            frame_index: 0,
+            recording_enabled: false,
+            recording: Vec::new(),
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            edit_in_progress: false,
+            frozen_velocities: None,
+            show_perf: true,
+            show_help: false,
+            frame_time: RollingAverage::default(),
+            physics_time: RollingAverage::default(),
+            ui_time: RollingAverage::default(),
         }
     }
 }
 
 impl App for InterplanetarySimulation {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings());
+    }
+
+    /// Keeps window positions and collapsed states along with the settings.
+    fn persist_egui_memory(&self) -> bool {
+        true
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
+        let update_start = Instant::now();
+        let mut physics_secs = 0.0;
+        if self.probe_plan.is_some() {
+            self.advance_probe_plan();
+            ctx.request_repaint();
+        }
+        self.save_screenshots(ctx);
+        if self.frame_dump {
+            let name = format!("frame_{:06}.png", self.frame_index);
+            self.frame_index += 1;
+            self.request_screenshot(ctx, &name);
+        }
+        if self.selected.is_some() {
+            egui::SidePanel::right("body_info").show(ctx, |ui| self.body_info_ui(ui));
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            let (rect, _response) =
+            let (rect, response) =
                 ui.allocate_exact_size(ui.available_size(), egui::Sense::click_and_drag());
 
+            // Registered after the main canvas so it sits on top for clicks.
+            let minimap = self.show_minimap.then(|| {
+                ui.interact(
+                    minimap_rect(rect),
+                    ui.id().with("minimap"),
+                    egui::Sense::click_and_drag(),
+                )
+            });
+
+            if response.clicked()
+                && let Some(pointer) = response.interact_pointer_pos()
+            {
+                if self.measure_mode {
+                    let point = self.screen_to_world(pointer, rect);
+                    if self.measure_live {
+                        self.measure_live = false;
+                    } else {
+                        self.measure = Some((point, point));
+                        self.measure_live = true;
+                    }
+                } else if self.planning_probe {
+                    self.planning_probe = false;
+                    if let Some(start) = self.selected {
+                        self.start_probe_plan(start, self.screen_to_world(pointer, rect));
+                    }
+                } else if ui.input(|i| i.modifiers.shift) {
+                    self.spawn_ring(self.screen_to_world(pointer, rect));
+                } else {
+                    self.selected = self.body_at(pointer, rect);
+                }
+            }
+            if response.double_clicked()
+                && !self.measure_mode
+                && !ui.input(|i| i.modifiers.shift)
+                && let Some(pointer) = response.interact_pointer_pos()
+            {
+                match self.body_at(pointer, rect) {
+                    Some(index) => self.fly_to_body(index, rect),
+                    None => {
+                        if let Some((center, zoom)) = self.frame_all_view(rect) {
+                            self.fly_to(None, center, zoom);
+                        }
+                    }
+                }
+            }
+
+            // In measure mode a drag also spans a measurement, fixed on release
+            if self.measure_mode
+                && response.drag_started_by(egui::PointerButton::Primary)
+                && let Some(origin) = ui.input(|i| i.pointer.press_origin())
+            {
+                let point = self.screen_to_world(origin, rect);
+                self.measure = Some((point, point));
+                self.measure_live = true;
+            }
+            if self.measure_mode && response.drag_stopped_by(egui::PointerButton::Primary) {
+                self.measure_live = false;
+            }
+            if self.measure_live
+                && let Some(pointer) = ui.input(|i| i.pointer.latest_pos())
+            {
+                let point = self.screen_to_world(pointer, rect);
+                if let Some((_, end)) = &mut self.measure {
+                    *end = point;
+                }
+            }
+            if response.clicked_by(egui::PointerButton::Secondary)
+                || ui.input(|i| i.key_pressed(egui::Key::Escape))
+            {
+                self.measure = None;
+                self.measure_live = false;
+            }
+
+            // Right-click opens a menu for the body under the pointer, or for empty space
+            if response.secondary_clicked()
+                && let Some(pointer) = response.interact_pointer_pos()
+            {
+                self.context_target = self.body_at(pointer, rect);
+                self.context_point = self.screen_to_world(pointer, rect);
+            }
+            response.context_menu(|ui| {
+                match self.context_target.filter(|&i| i < self.sim.bodies.len()) {
+                    Some(index) => self.body_context_menu(ui, index),
+                    None => self.background_context_menu(ui),
+                }
+            });
+
             // Handle camera movement
-            if ui.input(|i| i.pointer.primary_down()) {
-                self.camera_pos -= ui.input(|i| i.pointer.delta());
+            if response.dragged_by(egui::PointerButton::Secondary)
+                || response.dragged_by(egui::PointerButton::Middle)
+            {
+                let dt = ui.input(|i| i.stable_dt).max(f32::EPSILON);
+                let velocity = egui::lerp(
+                    self.pan_velocity..=response.drag_delta() / dt,
+                    PAN_VELOCITY_SMOOTHING,
+                );
+                self.camera_pos -= response.drag_delta() / self.zoom;
+                self.release_camera();
+                self.pan_velocity = velocity;
+            } else {
+                self.coast_camera(ui.input(|i| i.stable_dt));
+            }
+
+            // Shift+drag draws a box that selects every body inside it
+            if response.drag_started_by(egui::PointerButton::Primary)
+                && !self.measure_mode
+                && ui.input(|i| i.modifiers.shift)
+            {
+                self.box_select_start = ui.input(|i| i.pointer.press_origin());
+            }
+            if response.drag_stopped_by(egui::PointerButton::Primary)
+                && let Some(start) = self.box_select_start.take()
+                && let Some(release) = ui.input(|i| i.pointer.latest_pos())
+            {
+                let area = Rect::from_two_pos(start, release);
+                self.group = (0..self.sim.bodies.len())
+                    .filter(|&i| area.contains(self.world_to_screen(self.sim.bodies[i].pos, rect)))
+                    .collect();
+            }
+
+            // Drag a body to carry it around; letting go throws it with the pointer's motion
+            if response.drag_started_by(egui::PointerButton::Primary)
+                && !self.measure_mode
+                && self.box_select_start.is_none()
+                && let Some(origin) = ui.input(|i| i.pointer.press_origin())
+                && let Some(index) = self.body_at(origin, rect)
+            {
+                self.checkpoint();
+                self.held = Some(index);
+                self.held_target = self.sim.bodies[index].pos;
+                self.throw_velocity = Vector2::zeros();
+            }
+            if self.held.is_some() && response.dragged_by(egui::PointerButton::Primary) {
+                let delta = response.drag_delta() / self.zoom;
+                let delta = Vector2::new(delta.x, delta.y);
+                self.held_target += delta;
+                // Sim time runs `time_scale` times faster than the pointer moves; with time
+                // stopped there is no speed to give. Running backward, a body travels against
+                // its velocity, so the throw is flipped to keep it moving the way it was flung.
+                let direction = if self.reversed { -1.0 } else { 1.0 };
+                let dt = direction * ui.input(|i| i.unstable_dt) * self.time_scale;
+                self.throw_velocity = if dt != 0.0 {
+                    delta / dt
+                } else {
+                    Vector2::zeros()
+                };
+            }
+            if response.drag_stopped_by(egui::PointerButton::Primary)
+                && let Some(body) = self.held.take().and_then(|i| self.sim.bodies.get_mut(i))
+            {
+                body.vel = self.throw_velocity;
+            }
+            self.pin_held();
+
+            // Drag on empty space to create a body; the drag vector sets its velocity
+            if response.drag_started_by(egui::PointerButton::Primary)
+                && !self.measure_mode
+                && self.box_select_start.is_none()
+                && let Some(origin) = ui.input(|i| i.pointer.press_origin())
+                && self.body_at(origin, rect).is_none()
+            {
+                self.create_drag_start = Some(self.screen_to_world(origin, rect));
+            }
+            if response.drag_stopped_by(egui::PointerButton::Primary)
+                && let Some(start) = self.create_drag_start.take()
+                && let Some(release) = ui.input(|i| i.pointer.latest_pos())
+            {
+                let end = self.screen_to_world(release, rect);
+                let mut body = CelestialBody::new(start, self.new_body_mass, self.next_color());
+                body.vel = (end - start) * self.drag_velocity_scale;
+                self.checkpoint();
+                self.add_user_body(body);
+            }
+
+            if !ctx.wants_keyboard_input() {
+                self.handle_keyboard_camera(ui, rect);
+                let delete = ui.input(|i| {
+                    i.key_pressed(egui::Key::Delete) || i.key_pressed(egui::Key::Backspace)
+                });
+                if delete && !self.group.is_empty() {
+                    self.delete_group();
+                } else if delete && let Some(index) = self.selected.take() {
+                    self.remove_body(index);
+                }
+                if ui.input(|i| i.key_pressed(egui::Key::F)) {
+                    self.frame_all(rect);
+                }
+                if ui.input(|i| {
+                    i.key_pressed(egui::Key::H) || i.key_pressed(egui::Key::Questionmark)
+                }) {
+                    self.show_help = !self.show_help;
+                }
+                let (next, previous) = ui.input_mut(|i| {
+                    (
+                        i.consume_key(egui::Modifiers::NONE, egui::Key::Tab),
+                        i.consume_key(egui::Modifiers::SHIFT, egui::Key::Tab),
+                    )
+                });
+                if next || previous {
+                    self.cycle_selection(next);
+                }
+                let (undo, redo) = ui.input_mut(|i| {
+                    let shift_z = egui::Modifiers::COMMAND | egui::Modifiers::SHIFT;
+                    let redo = i.consume_key(shift_z, egui::Key::Z)
+                        || i.consume_key(egui::Modifiers::COMMAND, egui::Key::Y);
+                    (i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z), redo)
+                });
+                if undo {
+                    self.undo();
+                }
+                if redo {
+                    self.redo();
+                }
             }
-            // self.zoom *= (1.0 + ui.input(|i| i.raw.scroll_delta.y) / 200.0).max(0.1);
 
-            self.zoom *= f32::max(0.1, 1.0 + ui.input(|i| i.raw_scroll_delta.y) / 200.0);
+            if response.hovered() {
+                self.handle_scroll_and_gestures(ui, rect);
+            }
 
             let painter = ui.painter();
             painter.rect_filled(rect, 0.0, Color32::BLACK);
 
             // Simulation logic
-            let dt = ui.input(|i| i.stable_dt);
-            for i in 0..self.bodies.len() {
-                for j in 0..self.bodies.len() {
-                    if i == j {
-                        continue;
+            let frame_dt = ui.input(|i| i.unstable_dt);
+            let physics_start = Instant::now();
+            self.advance(frame_dt);
+            physics_secs = physics_start.elapsed().as_secs_f32();
+            self.frame_time.push(frame_dt);
+            self.physics_time.push(physics_secs);
+            self.sync_frame_source();
+            let frame_origin = self.frame_motion().map(|(pos, _)| pos);
+            if let (Some(old), Some(new)) = (self.frame_origin, frame_origin) {
+                self.camera_pos += egui::vec2(new.x - old.x, new.y - old.y);
+            }
+            self.frame_origin = frame_origin;
+            if let Some(body) = self.follow.and_then(|i| self.sim.bodies.get(i)) {
+                self.camera_pos = Pos2::new(body.pos.x, body.pos.y);
+            } else if self.lock_barycenter {
+                let center = self.sim.barycenter();
+                self.camera_pos = Pos2::new(center.x, center.y);
+            }
+            self.fly_camera();
+
+            let zoom = self.zoom;
+
+            if self.show_nebula {
+                draw_nebulae(painter, rect);
+            }
+            if self.show_starfield {
+                self.draw_starfield(painter, rect);
+            }
+            if self.show_potential {
+                self.draw_potential_field(painter, rect);
+            }
+            if self.show_grid {
+                self.draw_grid(painter, rect);
+            }
+
+            if self.sim.record_trails {
+                for body in &self.sim.bodies {
+                    let points: Vec<_> = match frame_origin {
+                        // Each point moves by how far the frame has moved since it was
+                        // recorded; points older than the frame's history are dropped.
+                        Some(origin) => {
+                            let history = &self.frame_history;
+                            body.trail
+                                .iter()
+                                .skip(body.trail.len().saturating_sub(history.len()))
+                                .zip(
+                                    history
+                                        .iter()
+                                        .skip(history.len().saturating_sub(body.trail.len())),
+                                )
+                                .map(|(&p, &o)| self.world_to_screen(p - o + origin, rect))
+                                .collect()
+                        }
+                        None => body
+                            .trail
+                            .iter()
+                            .map(|&p| self.world_to_screen(p, rect))
+                            .collect(),
+                    };
+                    let len = points.len() as f32;
+                    for (k, segment) in points.windows(2).enumerate() {
+                        let fade = (k + 1) as f32 / len;
+                        painter.line_segment(
+                            [segment[0], segment[1]],
+                            self.stroke(1.0, body.color.gamma_multiply(fade)),
+                        );
                     }
-                    let other = unsafe { &*(self.bodies.get(j).unwrap() as *const _) };
-                    self.bodies[i].apply_gravity(other);
                 }
             }
-            let camera_pos = self.camera_pos;
-            let zoom = self.zoom;
-            let center = rect.center();
 
-            for body in &mut self.bodies {
-                body.update(dt);
-                let screen_vec = (body.pos - Vector2::new(camera_pos.x, camera_pos.y)) * zoom;
-                let screen_pos = Pos2::new(center.x + screen_vec.x, center.y + screen_vec.y);
-                painter.circle_filled(screen_pos, body.radius * zoom, body.color);
+            if let Some(index) = self.selected.filter(|_| self.show_prediction) {
+                self.refresh_prediction(index);
+                let points: Vec<_> = self
+                    .prediction
+                    .iter()
+                    .flat_map(|prediction| &prediction.path)
+                    .map(|&p| self.world_to_screen(p, rect))
+                    .collect();
+                painter.extend(egui::Shape::dashed_line(
+                    &points,
+                    self.stroke(1.0, Color32::LIGHT_BLUE),
+                    6.0,
+                    4.0,
+                ));
+            }
+
+            if self.show_soi {
+                self.draw_spheres_of_influence(painter, rect);
+            }
+            self.draw_extent(painter, rect);
+
+            if self.color_by_speed && self.auto_speed_range {
+                let speeds = self.sim.bodies.iter().map(|b| b.vel.norm());
+                self.speed_range.0 = speeds.clone().fold(f32::INFINITY, f32::min);
+                self.speed_range.1 = speeds.fold(0.0, f32::max);
+            }
+            let (min_speed, max_speed) = self.speed_range;
+            let glow_mass = self.sim.bodies.iter().map(|b| b.mass).fold(0.0, f32::max);
+            self.refresh_diagnostics();
+            let well_depths = self
+                .diagnostics
+                .as_ref()
+                .map(|d| d.well_depths.clone())
+                .unwrap_or_default();
+
+            let mut dots = egui::Mesh::default();
+            let pixels_per_point = painter.pixels_per_point();
+            for (i, body) in self.sim.bodies.iter().enumerate() {
+                let screen_pos = self.world_to_screen(body.pos, rect);
+                let color = if self.color_by_speed {
+                    let span = (max_speed - min_speed).max(f32::EPSILON);
+                    viridis((body.vel.norm() - min_speed) / span)
+                } else {
+                    body.color
+                };
+                let color = match well_depths.get(i) {
+                    Some(&depth) => desaturate(color, depth * self.well_tint_strength),
+                    None => color,
+                };
+                let radius = self.display_radius(body) * zoom;
+                if self.show_glow && glow_mass > 0.0 {
+                    // The square root keeps planets faintly lit while asteroids stay dark.
+                    let strength = self.glow_intensity * (body.mass / glow_mass).max(0.0).sqrt();
+                    draw_glow(painter, screen_pos, radius, color, strength.min(1.0));
+                }
+                if radius * pixels_per_point < self.lod_radius {
+                    // Tiny bodies go into one mesh of squares instead of a circle each.
+                    let side = (radius * 2.0).max(1.0);
+                    dots.add_colored_rect(
+                        Rect::from_center_size(screen_pos, egui::Vec2::splat(side)),
+                        color,
+                    );
+                } else {
+                    match body.shape {
+                        BodyShape::Disc => {
+                            painter.circle_filled(screen_pos, radius, color);
+                        }
+                        BodyShape::Ring => {
+                            painter.circle_stroke(
+                                screen_pos,
+                                radius * (1.0 - RING_WIDTH / 2.0),
+                                egui::Stroke::new(radius * RING_WIDTH, color),
+                            );
+                        }
+                        BodyShape::Star => draw_star(painter, screen_pos, radius, color),
+                    }
+                }
+                if self.selected == Some(i) {
+                    painter.circle_stroke(
+                        screen_pos,
+                        radius + 4.0 * self.render_scale,
+                        self.stroke(2.0, Color32::WHITE),
+                    );
+                } else if self.group.contains(&i) {
+                    painter.circle_stroke(
+                        screen_pos,
+                        radius + 3.0 * self.render_scale,
+                        self.stroke(1.5, Color32::YELLOW),
+                    );
+                }
+            }
+            painter.add(dots);
+            self.draw_ripples(painter, rect);
+            if let Some(comparison) = &self.comparison {
+                for body in &comparison.sim.bodies {
+                    painter.circle_stroke(
+                        self.world_to_screen(body.pos, rect),
+                        (self.display_radius(body) * zoom).max(2.0),
+                        self.stroke(1.5, Color32::from_rgb(255, 80, 220)),
+                    );
+                }
+            }
+            if let Some(start) = self.box_select_start
+                && let Some(pointer) = ui.input(|i| i.pointer.latest_pos())
+            {
+                let area = Rect::from_two_pos(start, pointer);
+                painter.rect_filled(area, 0.0, Color32::from_rgba_unmultiplied(255, 255, 0, 20));
+                painter.rect_stroke(
+                    area,
+                    0.0,
+                    self.stroke(1.0, Color32::YELLOW),
+                    egui::StrokeKind::Inside,
+                );
+            }
+            if self.show_labels {
+                self.draw_labels(painter, rect);
+            }
+            if self.show_offscreen {
+                self.draw_offscreen_arrows(painter, rect);
+            }
+            if self.show_velocity {
+                let visible = rect.expand(ARROW_CULL_MARGIN);
+                let frame_vel = self.frame_motion().map_or(Vector2::zeros(), |(_, vel)| vel);
+                for body in &self.sim.bodies {
+                    let screen_pos = self.world_to_screen(body.pos, rect);
+                    if !visible.contains(screen_pos) {
+                        continue;
+                    }
+                    let v = (body.vel - frame_vel) * (self.velocity_arrow_scale * zoom);
+                    painter.arrow(
+                        screen_pos,
+                        egui::vec2(v.x, v.y),
+                        self.stroke(1.0, Color32::WHITE),
+                    );
+                }
+            }
+            // Stale, and indexed differently, after bodies are added or removed while paused.
+            let accelerations_fresh = self.sim.accelerations.len() == self.sim.bodies.len();
+            if self.show_acceleration && accelerations_fresh {
+                let visible = rect.expand(ARROW_CULL_MARGIN);
+                for (body, acc) in self.sim.bodies.iter().zip(&self.sim.accelerations) {
+                    let screen_pos = self.world_to_screen(body.pos, rect);
+                    if !visible.contains(screen_pos) {
+                        continue;
+                    }
+                    let a = acc * (self.acceleration_arrow_scale * zoom);
+                    painter.arrow(
+                        screen_pos,
+                        egui::vec2(a.x, a.y),
+                        self.stroke(1.0, Color32::from_rgb(255, 140, 60)),
+                    );
+                }
+            }
+            if self.sim.boundary != Boundary::Open {
+                let extent = self.sim.boundary_half_extent;
+                painter.rect_stroke(
+                    Rect::from_two_pos(
+                        self.world_to_screen(-extent, rect),
+                        self.world_to_screen(extent, rect),
+                    ),
+                    0.0,
+                    self.stroke(1.0, Color32::from_rgb(200, 120, 60)),
+                    egui::StrokeKind::Middle,
+                );
+            }
+            if self.show_barycenter && !self.sim.bodies.is_empty() {
+                let center = self.world_to_screen(self.sim.barycenter(), rect);
+                let stroke = self.stroke(1.5, Color32::from_rgb(255, 0, 255));
+                let arm = 8.0 * self.render_scale;
+                painter.line_segment(
+                    [center - egui::vec2(arm, 0.0), center + egui::vec2(arm, 0.0)],
+                    stroke,
+                );
+                painter.line_segment(
+                    [center - egui::vec2(0.0, arm), center + egui::vec2(0.0, arm)],
+                    stroke,
+                );
+            }
+            if let Some(start) = self.create_drag_start
+                && let Some(pointer) = ui.input(|i| i.pointer.latest_pos())
+            {
+                let origin = self.world_to_screen(start, rect);
+                painter.arrow(
+                    origin,
+                    pointer - origin,
+                    self.stroke(2.0, Color32::LIGHT_GREEN),
+                );
             }
-            // for body in &mut self.bodies {
-            //     body.update(dt);
-            //     let screen_pos = self.world_to_screen(body.pos, rect);
-            //     painter.circle_filled(screen_pos, body.radius * self.zoom, body.color);
-            // }
 
             // UI Controls
             egui::Window::new("Controls").show(ctx, |ui| {
-                if ui.button("Reset").clicked() {
-                    *self = Self::default();
+                ui.horizontal(|ui| {
+                    if ui.button("Reset").clicked() {
+                        self.load_scenario();
+                    }
+                    if ui.button("Reset View").clicked() {
+                        self.camera_pos = DEFAULT_CAMERA_POS;
+                        self.zoom = 1.0;
+                        self.pan_velocity = egui::Vec2::ZERO;
+                    }
+                    if ui
+                        .button("Frame All")
+                        .on_hover_text("Fit every body in the view (F)")
+                        .clicked()
+                    {
+                        self.frame_all(rect);
+                    }
+                    if ui
+                        .add_enabled(!self.undo_stack.is_empty(), egui::Button::new("Undo"))
+                        .clicked()
+                    {
+                        self.undo();
+                    }
+                    if ui
+                        .add_enabled(!self.redo_stack.is_empty(), egui::Button::new("Redo"))
+                        .clicked()
+                    {
+                        self.redo();
+                    }
+                });
+                let mut scenario = self.scenario;
+                egui::ComboBox::from_label("Scenario")
+                    .selected_text(scenario.label())
+                    .show_ui(ui, |ui| {
+                        for option in Scenario::ALL {
+                            ui.selectable_value(&mut scenario, option, option.label());
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.label("Seed");
+                    ui.add(egui::DragValue::new(&mut self.seed));
+                    if ui.button("Regenerate").clicked() {
+                        self.load_scenario();
+                    }
+                });
+                ui.collapsing("Scenario generation", |ui| {
+                    let params = &mut self.scenario_params;
+                    egui::ComboBox::from_label("Belt direction")
+                        .selected_text(params.belt_direction.label())
+                        .show_ui(ui, |ui| {
+                            for direction in OrbitDirection::ALL {
+                                ui.selectable_value(
+                                    &mut params.belt_direction,
+                                    direction,
+                                    direction.label(),
+                                );
+                            }
+                        });
+                    ui.add(
+                        egui::Slider::new(&mut params.belt_eccentricity, 0.0..=0.9)
+                            .text("Belt eccentricity"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut params.belt_mass_alpha, 0.0..=3.0)
+                            .text("Belt mass exponent"),
+                    )
+                    .on_hover_text("dN/dm ∝ m^-alpha; 0 is uniform");
+                    let (min, max) = &mut params.belt_mass_range;
+                    ui.add(
+                        egui::Slider::new(min, 0.1..=100.0)
+                            .logarithmic(true)
+                            .text("Lightest asteroid"),
+                    );
+                    ui.add(
+                        egui::Slider::new(max, 0.1..=100.0)
+                            .logarithmic(true)
+                            .text("Heaviest asteroid"),
+                    );
+                    *max = max.max(*min);
+                    ui.add(
+                        egui::Slider::new(&mut params.cluster_count, 10..=5000)
+                            .logarithmic(true)
+                            .text("Cluster bodies"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut params.cluster_radius, 50.0..=2000.0)
+                            .text("Cluster radius"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut params.cluster_velocity_dispersion, 0.0..=50.0)
+                            .text("Velocity dispersion"),
+                    );
+                    ui.label("Applied on the next Reset or Regenerate.");
+                });
+                if scenario != self.scenario {
+                    self.scenario = scenario;
+                    self.load_scenario();
                 }
-                if ui.button("Add Planet").clicked() {
-                    let mut rng = rand::rng();
-                    let pos =
-                        Vector2::new(rng.random_range(0.0..800.0), rng.random_range(0.0..600.0));
-                    let mass = rng.random_range(1500.0..2200.0);
-                    let color = Color32::from_rgb(
-                        rng.random_range(0..255),
-                        rng.random_range(0..255),
-                        rng.random_range(0..255),
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        self.file_status = Some(match self.save_scene(SAVE_PATH) {
+                            Ok(()) => format!("Saved to {SAVE_PATH}"),
+                            Err(err) => format!("Save failed: {err}"),
+                        });
+                    }
+                    if ui.button("Load").clicked() {
+                        self.file_status = Some(match self.load_scene(SAVE_PATH) {
+                            Ok(()) => format!("Loaded {SAVE_PATH}"),
+                            Err(err) => format!("Load failed: {err}"),
+                        });
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Screenshots to");
+                    ui.text_edit_singleline(&mut self.screenshot_dir);
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Save Screenshot").clicked() {
+                        let name = format!("screenshot_{:03}.png", self.screenshot_count);
+                        self.screenshot_count += 1;
+                        self.request_screenshot(ctx, &name);
+                    }
+                    if ui.checkbox(&mut self.frame_dump, "Dump frames").changed() {
+                        self.frame_index = 0;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.recording_enabled, "Record");
+                    if ui.button("Export CSV").clicked() {
+                        self.file_status = Some(match self.export_csv(CSV_PATH) {
+                            Ok(()) => {
+                                format!("Exported {} rows to {CSV_PATH}", self.recording.len())
+                            }
+                            Err(err) => format!("Export failed: {err}"),
+                        });
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.recording.clear();
+                    }
+                    ui.label(format!("{} rows", self.recording.len()));
+                });
+                if let Some(plan) = &self.probe_plan {
+                    let progress = plan.progress();
+                    ui.horizontal(|ui| {
+                        ui.add(egui::ProgressBar::new(progress).text("Planning probe…"));
+                        if ui.button("Cancel").clicked() {
+                            self.cancel_probe_plan();
+                        }
+                    });
+                }
+                if let Some(status) = &self.file_status {
+                    ui.label(status);
+                }
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Zero Velocities")
+                        .on_hover_text("Stop every body where it is")
+                        .clicked()
+                    {
+                        self.zero_velocities();
+                    }
+                    if self.frozen_velocities.is_none() {
+                        if ui
+                            .button("Freeze Velocities")
+                            .on_hover_text("Stop every body, keeping its velocity to restore")
+                            .clicked()
+                        {
+                            self.freeze_velocities();
+                        }
+                    } else if ui.button("Restore Velocities").clicked() {
+                        self.restore_velocities();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(if self.paused { "Play" } else { "Pause" })
+                        .clicked()
+                    {
+                        self.paused = !self.paused;
+                    }
+                    if ui.button("Single-Step").clicked() {
+                        self.step_once = true;
+                    }
+                    ui.label(format!(
+                        "t = {:.2} s, step {}",
+                        self.sim.sim_time, self.sim.step_count
+                    ));
+                });
+                ui.add(egui::Slider::new(&mut self.time_scale, 0.0..=10.0).text("Time scale"));
+                ui.add(egui::Slider::new(&mut self.substeps, 1..=16).text("Substeps"))
+                    .on_hover_text("Split every step into this many smaller ones");
+                ui.checkbox(&mut self.reversed, "Run backward");
+                if self.reversed {
+                    if self.sim.integrator != Integrator::VelocityVerlet {
+                        ui.colored_label(
+                            Color32::YELLOW,
+                            "Only Velocity Verlet retraces its own steps",
+                        );
+                    }
+                    if self.sim.damping.is_some()
+                        || self.sim.max_speed.is_some()
+                        || self.sim.collision_mode != CollisionMode::PassThrough
+                    {
+                        ui.colored_label(
+                            Color32::YELLOW,
+                            "Damping, the speed limit and collisions can't be undone, \
+                             so the path won't be retraced exactly",
+                        );
+                    }
+                }
+                ui.checkbox(&mut self.adaptive_dt, "Adaptive timestep");
+                if self.adaptive_dt {
+                    ui.add(
+                        egui::Slider::new(&mut self.dt_floor, 1.0e-5..=self.dt_ceiling)
+                            .logarithmic(true)
+                            .text("dt floor"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.dt_ceiling, self.dt_floor..=0.1)
+                            .logarithmic(true)
+                            .text("dt ceiling"),
                     );
-                    self.bodies.push(CelestialBody::new(pos, mass, color));
                 }
-            });
-            ui.ctx().request_repaint();
-        });
-    }
-}
-
-impl InterplanetarySimulation {
-    fn world_to_screen(&self, world_pos: Vector2<f32>, rect: Rect) -> Pos2 {
-        let center = rect.center();
-        let screen_vec =
-            (world_pos - Vector2::new(self.camera_pos.x, self.camera_pos.y)) * self.zoom;
-        Pos2::new(center.x + screen_vec.x, center.y + screen_vec.y)
-    }
-    // fn world_to_screen(&self, world_pos: Vector2<f32>, rect: Rect) -> Pos2 {
-
-    //     let center = rect.center();
-    //     (world_pos - Vector2::new(self.camera_pos.x, self.camera_pos.y)) * self.zoom
-    //         + Vector2::new(center.x, center.y)
-    // }
+                egui::ComboBox::from_label("Collisions")
+                    .selected_text(self.sim.collision_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in CollisionMode::ALL {
+                            ui.selectable_value(&mut self.sim.collision_mode, mode, mode.label());
+                        }
+                    });
+                ui.add_enabled(
+                    matches!(
+                        self.sim.collision_mode,
+                        CollisionMode::Elastic | CollisionMode::Fragment
+                    ),
+                    egui::Slider::new(&mut self.sim.restitution, 0.0..=1.0).text("Restitution"),
+                );
+                ui.add_enabled(
+                    self.sim.collision_mode == CollisionMode::Elastic,
+                    egui::Slider::new(&mut self.sim.friction, 0.0..=1.0).text("Friction"),
+                );
+                if self.sim.collision_mode == CollisionMode::Fragment {
+                    let fragmentation = &mut self.sim.fragmentation;
+                    ui.add(
+                        egui::Slider::new(&mut fragmentation.min_speed, 1.0..=1000.0)
+                            .logarithmic(true)
+                            .text("Shatter speed"),
+                    )
+                    .on_hover_text("Slower impacts merge");
+                    ui.add(
+                        egui::Slider::new(&mut fragmentation.max_pieces, 2..=32)
+                            .text("Max fragments"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut fragmentation.min_mass, 0.1..=1000.0)
+                            .logarithmic(true)
+                            .text("Min fragment mass"),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label("Boundary")
+                        .selected_text(self.sim.boundary.label())
+                        .show_ui(ui, |ui| {
+                            for mode in Boundary::ALL {
+                                ui.selectable_value(&mut self.sim.boundary, mode, mode.label());
+                            }
+                        });
+                    ui.add_enabled_ui(self.sim.boundary != Boundary::Open, |ui| {
+                        let extent = &mut self.sim.boundary_half_extent;
+                        ui.add(
+                            egui::DragValue::new(&mut extent.x)
+                                .range(10.0..=1.0e6)
+                                .prefix("w/2: "),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut extent.y)
+                                .range(10.0..=1.0e6)
+                                .prefix("h/2: "),
+                        );
+                    });
+                });
+                ui.add(
+                    egui::Slider::new(&mut self.sim.ejection_distance, 500.0..=20000.0)
+                        .logarithmic(true)
+                        .text("Ejection distance"),
+                );
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.sim.cull_ejected, "Cull ejected bodies");
+                    ui.label(format!("Ejected: {}", self.sim.ejected_count));
+                });
+                if cfg!(debug_assertions) {
+                    ui.checkbox(&mut self.sim.strict_numerics, "Strict numerics")
+                        .on_hover_text(
+                            "Panic on the first NaN or infinite body instead of removing it",
+                        );
+                }
+                ui.horizontal(|ui| {
+                    let mut limited = self.sim.max_speed.is_some();
+                    if ui.checkbox(&mut limited, "Limit speed").changed() {
+                        self.sim.max_speed = limited.then_some(DEFAULT_MAX_SPEED);
+                    }
+                    if let Some(max_speed) = &mut self.sim.max_speed {
+                        ui.add(
+                            egui::Slider::new(max_speed, 1.0..=10000.0)
+                                .logarithmic(true)
+                                .text("Max speed"),
+                        );
+                    } else {
+                        ui.label("Unlimited");
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let mut damped = self.sim.damping.is_some();
+                    if ui
+                        .checkbox(&mut damped, "Damping")
+                        .on_hover_text("Drag that bleeds off energy so clumps can settle")
+                        .changed()
+                    {
+                        self.sim.damping = damped.then_some(DEFAULT_DAMPING);
+                    }
+                    if let Some(damping) = &mut self.sim.damping {
+                        ui.add(
+                            egui::Slider::new(damping, 0.001..=1.0)
+                                .logarithmic(true)
+                                .text("Drag per second"),
+                        );
+                    } else {
+                        ui.label("None");
+                    }
+                });
+                let mut following = self.follow.is_some();
+                if ui
+                    .add_enabled(
+                        following || self.selected.is_some(),
+                        egui::Checkbox::new(&mut following, "Follow Selected"),
+                    )
+                    .changed()
+                {
+                    self.follow = if following { self.selected } else { None };
+                    self.lock_barycenter = false;
+                }
+                egui::ComboBox::from_label("Reference frame")
+                    .selected_text(self.reference_frame.label())
+                    .show_ui(ui, |ui| {
+                        for frame in ReferenceFrame::ALL {
+                            ui.selectable_value(&mut self.reference_frame, frame, frame.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Draws trails and velocities relative to the barycenter or the \
+                         selected body; the physics is unchanged",
+                    );
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.pan_inertia, "Pan inertia")
+                        .on_hover_text("Keep gliding after a camera drag is released");
+                    ui.add_enabled(
+                        self.pan_inertia,
+                        egui::Slider::new(&mut self.pan_friction, 0.5..=20.0)
+                            .logarithmic(true)
+                            .text("Friction"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_barycenter, "Show Barycenter");
+                    if ui
+                        .checkbox(&mut self.lock_barycenter, "Lock camera to barycenter")
+                        .changed()
+                    {
+                        self.follow = None;
+                    }
+                });
+                let mut radius_mode = self.sim.radius_mode;
+                egui::ComboBox::from_label("Radius")
+                    .selected_text(radius_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in RadiusMode::ALL {
+                            ui.selectable_value(&mut radius_mode, mode, mode.label());
+                        }
+                    });
+                if radius_mode != self.sim.radius_mode {
+                    self.sim.set_radius_mode(radius_mode);
+                }
+                ui.add(
+                    egui::Slider::new(&mut self.radius_scale, 0.1..=10.0)
+                        .logarithmic(true)
+                        .text("Draw scale"),
+                );
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::Slider::new(&mut self.render_scale, 0.5..=4.0)
+                            .logarithmic(true)
+                            .text("Render scale"),
+                    );
+                    ui.label(format!("{:.2} px/pt", ui.ctx().pixels_per_point()));
+                });
+                ui.add(
+                    egui::Slider::new(&mut self.lod_radius, 0.0..=5.0)
+                        .text("Point below radius (px)"),
+                )
+                .on_hover_text("Draw smaller bodies as plain squares, which is much faster");
+                ui.horizontal(|ui| {
+                    if ui
+                        .checkbox(&mut self.sim.record_trails, "Show Trails")
+                        .changed()
+                        && !self.sim.record_trails
+                    {
+                        for body in &mut self.sim.bodies {
+                            body.trail.clear();
+                        }
+                    }
+                    ui.add(egui::Slider::new(&mut self.sim.trail_length, 2..=1000).text("Length"));
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_minimap, "Show Minimap");
+                    ui.checkbox(&mut self.show_grid, "Show Grid");
+                    ui.checkbox(&mut self.show_perf, "Show Performance");
+                    ui.toggle_value(&mut self.show_help, "Help (H)");
+                });
+                ui.checkbox(&mut self.show_soi, "Show SOI");
+                egui::ComboBox::from_label("Extent")
+                    .selected_text(self.extent_overlay.label())
+                    .show_ui(ui, |ui| {
+                        for overlay in ExtentOverlay::ALL {
+                            ui.selectable_value(&mut self.extent_overlay, overlay, overlay.label());
+                        }
+                    });
+                if ui
+                    .checkbox(&mut self.measure_mode, "Measure")
+                    .on_hover_text(
+                        "Click two points or drag to measure; right-click or Escape clears",
+                    )
+                    .changed()
+                {
+                    self.measure_live = false;
+                }
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_starfield, "Starfield");
+                    ui.add(egui::Slider::new(&mut self.star_density, 0.1..=10.0).text("Density"));
+                    ui.checkbox(&mut self.show_nebula, "Nebula");
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_well_tint, "Time Dilation Tint")
+                        .on_hover_text("Gray out bodies deep in a gravity well. Visual only.");
+                    ui.add(
+                        egui::Slider::new(&mut self.well_tint_strength, 0.0..=1.0).text("Strength"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_glow, "Glow");
+                    ui.add(
+                        egui::Slider::new(&mut self.glow_intensity, 0.0..=1.0).text("Intensity"),
+                    );
+                });
+                ui.checkbox(&mut self.show_ripples, "Merge Ripples");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_potential, "Show Potential");
+                    ui.add(
+                        egui::Slider::new(&mut self.potential_cell_size, 4.0..=64.0)
+                            .text("Cell size"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.color_by_speed, "Color by Speed");
+                    ui.checkbox(&mut self.auto_speed_range, "Auto range");
+                });
+                if self.color_by_speed && !self.auto_speed_range {
+                    ui.add(
+                        egui::Slider::new(&mut self.speed_range.0, 0.0..=500.0).text("Min speed"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.speed_range.1, 0.0..=500.0).text("Max speed"),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_velocity, "Show Velocity Vectors");
+                    ui.add(
+                        egui::Slider::new(&mut self.velocity_arrow_scale, 0.01..=10.0)
+                            .logarithmic(true)
+                            .text("Scale"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_acceleration, "Show Force Vectors")
+                        .on_hover_text("Net gravitational acceleration, i.e. force per unit mass");
+                    ui.add(
+                        egui::Slider::new(&mut self.acceleration_arrow_scale, 0.01..=100.0)
+                            .logarithmic(true)
+                            .text("Scale"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_labels, "Show Labels");
+                    let mut heavy_only = self.label_min_mass.is_some();
+                    if ui.checkbox(&mut heavy_only, "Only above mass").changed() {
+                        self.label_min_mass = heavy_only.then_some(DEFAULT_LABEL_MIN_MASS);
+                    }
+                    if let Some(min_mass) = &mut self.label_min_mass {
+                        ui.add(egui::Slider::new(min_mass, 1.0..=1.0e11).logarithmic(true));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_offscreen, "Point at Off-screen Bodies");
+                    let mut heavy_only = self.offscreen_min_mass.is_some();
+                    if ui.checkbox(&mut heavy_only, "Only above mass").changed() {
+                        self.offscreen_min_mass = heavy_only.then_some(DEFAULT_LABEL_MIN_MASS);
+                    }
+                    if let Some(min_mass) = &mut self.offscreen_min_mass {
+                        ui.add(egui::Slider::new(min_mass, 1.0..=1.0e11).logarithmic(true));
+                    }
+                });
+                ui.add(
+                    egui::Slider::new(&mut self.sim.gravity.gravity_constant, 1.0e-8..=1.0)
+                        .logarithmic(true)
+                        .text("G"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.sim.gravity.softening, 0.0..=20.0)
+                        .text("Softening"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.sim.gravity.theta, 0.1..=1.5)
+                        .text("Barnes-Hut theta"),
+                );
+                ui.checkbox(&mut self.sim.gravity.auto_orbit_assist, "Auto orbit assist")
+                    .on_hover_text(
+                        "Push light bodies sideways near much heavier ones. Not physical; \
+                         adds energy.",
+                    );
+                ui.collapsing("Compare integrators", |ui| self.comparison_ui(ui));
+                egui::ComboBox::from_label("Integrator")
+                    .selected_text(self.sim.integrator.label())
+                    .show_ui(ui, |ui| {
+                        for integrator in Integrator::ALL {
+                            ui.selectable_value(
+                                &mut self.sim.integrator,
+                                integrator,
+                                integrator.label(),
+                            );
+                        }
+                    });
+                if ui.button("Add Planet").clicked() {
+                    // Somewhere in the middle half of the current view.
+                    let mut rng = rand::rng();
+                    let half = rect.size() / (4.0 * self.zoom);
+                    let pos = self.screen_to_world(rect.center(), rect)
+                        + Vector2::new(
+                            rng.random_range(-half.x..=half.x),
+                            rng.random_range(-half.y..=half.y),
+                        );
+                    self.checkpoint();
+                    let color = self.next_color();
+                    self.add_user_body(CelestialBody::new(pos, self.new_body_mass, color));
+                }
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::Slider::new(&mut self.new_body_mass, 1.0..=1.0e5)
+                            .logarithmic(true)
+                            .text("New body mass"),
+                    );
+                    let radius = self
+                        .sim
+                        .radius_mode
+                        .radius(self.new_body_mass, DEFAULT_DENSITY);
+                    ui.label(format!(
+                        "{:.2} Earth masses, radius {radius:.1}",
+                        self.new_body_mass / EARTH_MASS
+                    ));
+                });
+                ui.add(
+                    egui::Slider::new(&mut self.drag_velocity_scale, 0.0..=5.0)
+                        .text("Drag velocity scale"),
+                );
+                ui.horizontal(|ui| {
+                    let mut limited = self.max_bodies.is_some();
+                    if ui.checkbox(&mut limited, "Limit bodies").changed() {
+                        self.max_bodies = limited.then_some(DEFAULT_MAX_BODIES);
+                    }
+                    if let Some(max_bodies) = &mut self.max_bodies {
+                        ui.add(egui::Slider::new(max_bodies, 10..=10_000).logarithmic(true));
+                        ui.checkbox(&mut self.recycle_oldest, "Recycle oldest")
+                            .on_hover_text(
+                                "Make room by removing the oldest light, unpinned body \
+                                 that isn't selected",
+                            );
+                    }
+                });
+                ui.separator();
+                ui.label("Shift+click spawns a ring of asteroids");
+                ui.add(egui::Slider::new(&mut self.ring_count, 1..=500).text("Ring count"));
+                ui.add(
+                    egui::Slider::new(&mut self.ring_radii.0, 1.0..=self.ring_radii.1)
+                        .text("Inner radius"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.ring_radii.1, self.ring_radii.0..=2000.0)
+                        .text("Outer radius"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.ring_mass.0, 0.1..=self.ring_mass.1)
+                        .logarithmic(true)
+                        .text("Min mass"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.ring_mass.1, self.ring_mass.0..=1000.0)
+                        .logarithmic(true)
+                        .text("Max mass"),
+                );
+            });
+
+            let ((kinetic, potential, total), angular_momentum) = self
+                .diagnostics
+                .as_ref()
+                .map(|d| (d.energy, d.angular_momentum))
+                .unwrap_or_default();
+            egui::Window::new("Energy")
+                .default_pos(rect.right_top() + egui::vec2(-220.0, 10.0))
+                .show(ctx, |ui| {
+                    ui.label(format!("Kinetic:   {kinetic:.4}"));
+                    ui.label(format!("Potential: {potential:.4}"));
+                    ui.label(format!("Total:     {total:.4}"));
+                    ui.checkbox(&mut self.show_energy_plot, "Plot total energy");
+                    if self.show_energy_plot {
+                        history_plot(
+                            ui,
+                            "energy_plot",
+                            &self.energy_history,
+                            DIAGNOSTICS_INTERVAL as f64,
+                            "wall-clock seconds",
+                        );
+                    }
+                    ui.label(format!("Angular momentum: {angular_momentum:.4}"));
+                    ui.checkbox(&mut self.show_momentum_plot, "Plot angular momentum");
+                    if self.show_momentum_plot {
+                        history_plot(
+                            ui,
+                            "momentum_plot",
+                            &self.momentum_history,
+                            DIAGNOSTICS_INTERVAL as f64,
+                            "wall-clock seconds",
+                        );
+                    }
+                });
+            if !self.group.is_empty() {
+                egui::Window::new("Selection")
+                    .default_pos(rect.left_bottom() + egui::vec2(10.0, -160.0))
+                    .show(ctx, |ui| self.group_ui(ui));
+            }
+            egui::Window::new("Help")
+                .open(&mut self.show_help)
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    for (section, bindings) in HELP {
+                        ui.strong(section);
+                        egui::Grid::new(section).striped(true).show(ui, |ui| {
+                            for (input, action) in bindings {
+                                ui.monospace(*input);
+                                ui.label(*action);
+                                ui.end_row();
+                            }
+                        });
+                        ui.add_space(6.0);
+                    }
+                });
+            egui::Window::new("Events")
+                .default_pos(rect.right_top() + egui::vec2(-220.0, 220.0))
+                .default_open(false)
+                .show(ctx, |ui| self.events_ui(ui));
+            if let Some(minimap) = minimap {
+                self.minimap_ui(&minimap, painter, rect);
+            }
+            if self.show_tides
+                && let Some(index) = self.selected.filter(|&i| i < self.sim.bodies.len())
+            {
+                self.draw_tides(painter, rect, index);
+            }
+            if let Some((start, end)) = self.measure {
+                self.draw_measurement(painter, rect, start, end);
+            }
+            if self.show_perf {
+                self.draw_perf_overlay(painter, rect);
+            }
+            ui.ctx().request_repaint();
+        });
+        self.ui_time
+            .push(update_start.elapsed().as_secs_f32() - physics_secs);
+    }
 }
 
+impl InterplanetarySimulation {
+    /// Feeds `frame_dt` of real time into the accumulator and runs as many fixed steps as fit.
+    fn advance(&mut self, frame_dt: f32) {
+        if self.step_once {
+            self.step_once = false;
+            let dt = self.next_step_dt();
+            self.physics_step(dt);
+            return;
+        }
+        if self.paused {
+            return;
+        }
+
+        self.accumulator += frame_dt;
+        let mut steps = 0;
+        while steps < MAX_STEPS_PER_FRAME {
+            let dt = self.next_step_dt();
+            if self.accumulator < dt {
+                break;
+            }
+            self.physics_step(dt);
+            self.accumulator -= dt;
+            steps += 1;
+        }
+        // Drop whatever is left after a long stall instead of trying to catch up.
+        if steps == MAX_STEPS_PER_FRAME {
+            self.accumulator = self.accumulator.min(self.step_dt);
+        }
+    }
+
+    /// Picks the length of the next step: `fixed_dt`, or when adaptive, a small fraction
+    /// of the time until the closest pair could meet, bounded by the floor and ceiling.
+    fn next_step_dt(&mut self) -> f32 {
+        if !self.adaptive_dt {
+            self.step_dt = self.fixed_dt;
+            return self.step_dt;
+        }
+        // `min_approach_time` is in simulated seconds; steps are measured before time scaling.
+        // Encounters too far off to pull the step below the ceiling needn't be searched for.
+        let horizon = self.dt_ceiling * self.time_scale / ADAPTIVE_DT_FRACTION;
+        let target = self.sim.min_approach_time(horizon) * ADAPTIVE_DT_FRACTION / self.time_scale;
+        self.step_dt = target
+            .min(self.step_dt * ADAPTIVE_DT_GROWTH)
+            .max(self.dt_floor)
+            .min(self.dt_ceiling);
+        self.step_dt
+    }
+
+    fn physics_step(&mut self, step_dt: f32) {
+        let direction = if self.reversed { -1.0 } else { 1.0 };
+        let dt = direction * step_dt * self.time_scale / self.substeps as f32;
+        for _ in 0..self.substeps {
+            if let Some(remap) = self.sim.step(dt) {
+                self.apply_remap(&remap);
+            }
+            self.step_comparison(dt);
+            if self.show_ripples {
+                self.spawn_ripples();
+            }
+            self.pin_held();
+            self.record_frame_origin();
+            if self.recording_enabled {
+                self.record_step();
+            }
+        }
+    }
+
+    /// Starts a ripple at every merge the last step logged.
+    fn spawn_ripples(&mut self) {
+        let now = Instant::now();
+        for event in self.sim.events.iter().take(self.sim.recent_events) {
+            if let SimEventKind::Merge {
+                resulting_mass,
+                position,
+                ..
+            } = event.kind
+            {
+                self.ripples.push_back(Ripple {
+                    origin: position,
+                    started: now,
+                    radius: self.sim.radius_mode.radius(resulting_mass, DEFAULT_DENSITY),
+                });
+            }
+        }
+        while self.ripples.len() > MAX_RIPPLES {
+            self.ripples.pop_front();
+        }
+    }
+
+    /// Draws the live ripples, dropping those that have faded out.
+    fn draw_ripples(&mut self, painter: &egui::Painter, rect: Rect) {
+        self.ripples
+            .retain(|ripple| ripple.started.elapsed().as_secs_f32() < RIPPLE_LIFETIME);
+        for ripple in &self.ripples {
+            let t = ripple.started.elapsed().as_secs_f32() / RIPPLE_LIFETIME;
+            let start = ripple.radius * self.radius_scale * self.render_scale * self.zoom;
+            painter.circle_stroke(
+                self.world_to_screen(ripple.origin, rect),
+                start + RIPPLE_REACH * self.render_scale * t,
+                self.stroke(2.0 * (1.0 - t), Color32::WHITE.gamma_multiply(1.0 - t)),
+            );
+        }
+    }
+
+    /// Steps the branch as far as the main simulation just went and records how far apart
+    /// the tracked body has drifted.
+    fn step_comparison(&mut self, dt: f32) {
+        let Some(comparison) = &mut self.comparison else {
+            return;
+        };
+        if let Some(remap) = comparison.sim.step(dt) {
+            comparison.tracked.1 = remap_index(comparison.tracked.1, &remap);
+        }
+        let main = comparison.tracked.0.and_then(|i| self.sim.bodies.get(i));
+        let branch = comparison
+            .tracked
+            .1
+            .and_then(|i| comparison.sim.bodies.get(i));
+        if let (Some(main), Some(branch)) = (main, branch) {
+            comparison
+                .divergence
+                .push_back((main.pos - branch.pos).norm());
+            while comparison.divergence.len() > ENERGY_HISTORY_LEN {
+                comparison.divergence.pop_front();
+            }
+        }
+    }
+
+    /// Clones the simulation into a branch that steps with `comparison_integrator`,
+    /// tracking the selected body, or the heaviest when nothing is selected.
+    fn branch_comparison(&mut self) {
+        let mut sim = self.sim.clone();
+        sim.integrator = self.comparison_integrator;
+        let tracked = self.selected.or_else(|| {
+            (0..self.sim.bodies.len())
+                .max_by(|&a, &b| self.sim.bodies[a].mass.total_cmp(&self.sim.bodies[b].mass))
+        });
+        self.comparison = Some(Comparison {
+            sim,
+            tracked: (tracked, tracked),
+            divergence: VecDeque::new(),
+        });
+    }
+
+    fn comparison_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Branch integrator")
+                .selected_text(self.comparison_integrator.label())
+                .show_ui(ui, |ui| {
+                    for integrator in Integrator::ALL {
+                        ui.selectable_value(
+                            &mut self.comparison_integrator,
+                            integrator,
+                            integrator.label(),
+                        );
+                    }
+                });
+            if ui.button("Branch").clicked() {
+                self.branch_comparison();
+            }
+            if ui
+                .add_enabled(self.comparison.is_some(), egui::Button::new("Stop"))
+                .clicked()
+            {
+                self.comparison = None;
+            }
+        });
+        let Some(comparison) = &self.comparison else {
+            ui.label("Branch the current state to step it with both integrators at once.");
+            return;
+        };
+        ui.label(format!(
+            "{} vs {}",
+            self.sim.integrator.label(),
+            comparison.sim.integrator.label()
+        ));
+        match (comparison.tracked.0, comparison.divergence.back()) {
+            (Some(i), Some(distance)) => {
+                ui.label(format!("Body #{i} is {distance:.4} apart"));
+            }
+            _ => {
+                ui.label("The tracked body is gone");
+            }
+        }
+        history_plot(
+            ui,
+            "divergence_plot",
+            &comparison.divergence,
+            1.0,
+            "physics steps",
+        );
+    }
+
+    /// Position and velocity of the reference frame's origin, or `None` in the world frame
+    /// and when there is no body to follow.
+    fn frame_motion(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        match self.reference_frame {
+            ReferenceFrame::World => None,
+            ReferenceFrame::Barycenter => (!self.sim.bodies.is_empty())
+                .then(|| (self.sim.barycenter(), self.sim.barycenter_velocity())),
+            ReferenceFrame::Body => self
+                .selected
+                .and_then(|i| self.sim.bodies.get(i))
+                .map(|body| (body.pos, body.vel)),
+        }
+    }
+
+    /// Starts the frame history over when the frame or the frame body has changed.
+    fn sync_frame_source(&mut self) {
+        let body = match self.reference_frame {
+            ReferenceFrame::Body => self.selected,
+            _ => None,
+        };
+        if self.frame_source != (self.reference_frame, body) {
+            self.frame_source = (self.reference_frame, body);
+            self.frame_history.clear();
+            self.frame_origin = None;
+        }
+    }
+
+    /// Records the frame origin alongside the trail points the step just recorded.
+    fn record_frame_origin(&mut self) {
+        self.sync_frame_source();
+        if !self.sim.record_trails {
+            return;
+        }
+        if let Some((origin, _)) = self.frame_motion() {
+            self.frame_history.push_back(origin);
+            while self.frame_history.len() > self.sim.trail_length {
+                self.frame_history.pop_front();
+            }
+        }
+    }
+
+    fn record_step(&mut self) {
+        if self.recording.len() + self.sim.bodies.len() > MAX_RECORDED_ROWS {
+            self.recording_enabled = false;
+            self.file_status = Some(format!(
+                "Recording stopped at {MAX_RECORDED_ROWS} rows; export or clear it"
+            ));
+            return;
+        }
+        let (step, time) = (self.sim.step_count, self.sim.sim_time);
+        self.recording
+            .extend(self.sim.bodies.iter().map(|b| TrajectorySample {
+                step,
+                time,
+                body: b.id,
+                pos: b.pos,
+                vel: b.vel,
+            }));
+    }
+
+    /// Writes the recording with one row per body per step. `body` is the body's id, so
+    /// each body's rows stay its own through merges and removals of others.
+    fn export_csv(&self, path: &str) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(out, "step,time,body,x,y,vx,vy")?;
+        for sample in &self.recording {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{}",
+                sample.step,
+                sample.time,
+                sample.body,
+                sample.pos.x,
+                sample.pos.y,
+                sample.vel.x,
+                sample.vel.y
+            )?;
+        }
+        out.flush()
+    }
+
+    fn events_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(format!("{} events", self.sim.events.len()));
+            if ui.button("Clear").clicked() {
+                self.sim.events.clear();
+            }
+        });
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for event in &self.sim.events {
+                    ui.label(event.describe());
+                }
+            });
+    }
+
+    /// Heatmap of the potential per unit mass, `sum(-G*m/r)`, sampled at the center of
+    /// each screen cell. Shading is logarithmic and relative to the cells on screen, so
+    /// both deep wells and the shallow saddles between them stay visible. Repulsive bodies
+    /// raise the potential instead, and the hills around them shade as the floor.
+    fn draw_potential_field(&self, painter: &egui::Painter, rect: Rect) {
+        let cell = self.potential_cell_size.max(1.0);
+        let cols = (rect.width() / cell).ceil() as usize;
+        let rows = (rect.height() / cell).ceil() as usize;
+        let mut cells = Vec::with_capacity(cols * rows);
+        for row in 0..rows {
+            for col in 0..cols {
+                let min = rect.min + egui::vec2(col as f32, row as f32) * cell;
+                let cell_rect = Rect::from_min_size(min, egui::Vec2::splat(cell));
+                let world = self.screen_to_world(cell_rect.center(), rect);
+                let potential: f32 = self
+                    .sim
+                    .bodies
+                    .iter()
+                    .map(|b| {
+                        let dist = (b.pos - world).norm().max(f32::EPSILON);
+                        let sign = if b.repulsive { -1.0 } else { 1.0 };
+                        self.sim.gravity.pair_potential(1.0, b.mass, dist) * sign
+                    })
+                    .sum();
+                cells.push((cell_rect, (-potential).max(f32::MIN_POSITIVE).ln()));
+            }
+        }
+
+        let (lo, hi) = cells
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &(_, v)| {
+                (lo.min(v), hi.max(v))
+            });
+        let span = (hi - lo).max(f32::EPSILON);
+        let mut mesh = egui::Mesh::default();
+        for (cell_rect, depth) in cells {
+            let color = viridis((depth - lo) / span).gamma_multiply(0.5);
+            mesh.add_colored_rect(cell_rect.intersect(rect), color);
+        }
+        painter.add(mesh);
+    }
+
+    /// Recomputes [`Diagnostics`] once they are [`DIAGNOSTICS_INTERVAL`] old, or sooner when
+    /// the well depths no longer line up with the bodies, sampling the histories while
+    /// running.
+    fn refresh_diagnostics(&mut self) {
+        let depth_count = if self.show_well_tint {
+            self.sim.bodies.len()
+        } else {
+            0
+        };
+        if let Some(diagnostics) = &self.diagnostics
+            && diagnostics.taken.elapsed().as_secs_f32() < DIAGNOSTICS_INTERVAL
+            && diagnostics.well_depths.len() == depth_count
+        {
+            return;
+        }
+        let energy = self.sim.total_energy();
+        let angular_momentum = self.sim.total_angular_momentum();
+        if !self.paused {
+            for (history, sample) in [
+                (&mut self.energy_history, energy.2),
+                (&mut self.momentum_history, angular_momentum),
+            ] {
+                history.push_back(sample);
+                while history.len() > ENERGY_HISTORY_LEN {
+                    history.pop_front();
+                }
+            }
+        }
+        self.diagnostics = Some(Diagnostics {
+            energy,
+            angular_momentum,
+            well_depths: if self.show_well_tint {
+                self.well_depths()
+            } else {
+                Vec::new()
+            },
+            taken: Instant::now(),
+        });
+    }
+
+    /// How deep each body sits in the others' gravity well, from `0.0` for the shallowest
+    /// to `1.0` for the deepest. Like the potential heatmap, the scale is logarithmic so a
+    /// single star doesn't wash out every other difference.
+    fn well_depths(&self) -> Vec<f32> {
+        let bodies = &self.sim.bodies;
+        let depths: Vec<f32> = bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| {
+                let potential: f32 = bodies
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, other)| {
+                        let dist = (other.pos - body.pos).norm().max(f32::EPSILON);
+                        let sign = if other.repulsive { -1.0 } else { 1.0 };
+                        self.sim.gravity.pair_potential(1.0, other.mass, dist) * sign
+                    })
+                    .sum();
+                (-potential).max(f32::MIN_POSITIVE).ln()
+            })
+            .collect();
+        let (lo, hi) = depths
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| {
+                (lo.min(v), hi.max(v))
+            });
+        let span = (hi - lo).max(f32::EPSILON);
+        depths.into_iter().map(|d| (d - lo) / span).collect()
+    }
+
+    /// Laplace sphere of influence of every body but the heaviest, `a * (m / M)^(2/5)`
+    /// with `a` the distance to the heaviest body of mass `M`.
+    fn draw_spheres_of_influence(&self, painter: &egui::Painter, rect: Rect) {
+        let Some((dominant, central)) = self
+            .sim
+            .bodies
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.mass.total_cmp(&b.1.mass))
+        else {
+            return;
+        };
+        let stroke = self.stroke(1.0, Color32::from_rgba_unmultiplied(120, 200, 255, 60));
+        for (i, body) in self.sim.bodies.iter().enumerate() {
+            if i == dominant {
+                continue;
+            }
+            let a = (body.pos - central.pos).norm();
+            let soi = a * (body.mass / central.mass).powf(0.4);
+            painter.circle_stroke(
+                self.world_to_screen(body.pos, rect),
+                soi * self.zoom,
+                stroke,
+            );
+        }
+    }
+
+    /// Tidal acceleration at the near and far sides of body `index`: the dominant
+    /// attractor's pull there minus its pull at the center. Both arrows point away from
+    /// the center along the line to the attractor, which is the stretch tides come from.
+    fn draw_tides(&self, painter: &egui::Painter, rect: Rect, index: usize) {
+        let body = &self.sim.bodies[index];
+        let Some(attractor) = self.dominant_body(body.pos, Some(index)) else {
+            return;
+        };
+        let attractor = &self.sim.bodies[attractor];
+        let gravity = &self.sim.gravity;
+        let pull_at = |p: Vector2<f32>| gravity.pull(attractor.pos - p, attractor.mass);
+        // A body sitting right on its attractor has no line to stretch along.
+        let Some(toward) = (attractor.pos - body.pos).try_normalize(f32::EPSILON) else {
+            return;
+        };
+        let center = pull_at(body.pos);
+        let stroke = self.stroke(2.0, Color32::from_rgb(120, 200, 255));
+        let mut strength: f32 = 0.0;
+        for side in [1.0, -1.0] {
+            let point = body.pos + toward * (body.radius * side);
+            let tide = pull_at(point) - center;
+            strength = strength.max(tide.norm());
+            let Some(dir) = tide.try_normalize(f32::EPSILON) else {
+                continue;
+            };
+            let v = dir * (TIDE_ARROW_LENGTH * self.render_scale);
+            painter.arrow(
+                self.world_to_screen(point, rect),
+                egui::vec2(v.x, v.y),
+                stroke,
+            );
+        }
+        let screen_radius = self.display_radius(body) * self.zoom;
+        painter.text(
+            self.world_to_screen(body.pos, rect)
+                + egui::vec2(screen_radius + 6.0, screen_radius + 6.0),
+            egui::Align2::LEFT_TOP,
+            format!("tide {strength:.3e}"),
+            egui::FontId::monospace(12.0 * self.render_scale),
+            stroke.color,
+        );
+    }
+
+    /// Ruler line between two world points, labelled with its world length and its angle
+    /// counter-clockwise from the screen's +x axis.
+    fn draw_measurement(
+        &self,
+        painter: &egui::Painter,
+        rect: Rect,
+        start: Vector2<f32>,
+        end: Vector2<f32>,
+    ) {
+        let (a, b) = (
+            self.world_to_screen(start, rect),
+            self.world_to_screen(end, rect),
+        );
+        let stroke = self.stroke(1.5, Color32::from_rgb(255, 220, 120));
+        painter.line_segment([a, b], stroke);
+        painter.circle_filled(a, 3.0 * self.render_scale, stroke.color);
+        painter.circle_filled(b, 3.0 * self.render_scale, stroke.color);
+        let delta = end - start;
+        // World y grows downwards on screen, so flip it for a conventional angle.
+        let angle = (-delta.y).atan2(delta.x).to_degrees();
+        painter.text(
+            b + egui::vec2(8.0, -8.0),
+            egui::Align2::LEFT_BOTTOM,
+            format!("{:.1} ({angle:.1}°)", delta.norm()),
+            egui::FontId::monospace(12.0 * self.render_scale),
+            stroke.color,
+        );
+    }
+
+    /// FPS, body count and where the frame time goes, in the view's bottom-left corner.
+    fn draw_perf_overlay(&self, painter: &egui::Painter, rect: Rect) {
+        let frame = self.frame_time.mean();
+        let fps = if frame > 0.0 { 1.0 / frame } else { 0.0 };
+        let substeps = match self.substeps {
+            1 => String::new(),
+            n => format!(" ({n} substeps)"),
+        };
+        let bodies = match self.max_bodies {
+            Some(cap) => format!("{}/{cap} bodies", self.sim.bodies.len()),
+            None => format!("{} bodies", self.sim.bodies.len()),
+        };
+        let text = format!(
+            "{fps:.0} FPS ({:.1} ms)\n{bodies}\nphysics {:.2} ms, UI {:.2} ms\ndt {:.5} s{substeps}",
+            frame * 1000.0,
+            self.physics_time.mean() * 1000.0,
+            self.ui_time.mean() * 1000.0,
+            self.step_dt * self.time_scale / self.substeps as f32,
+        );
+        painter.text(
+            rect.left_bottom() + egui::vec2(10.0, -10.0) * self.render_scale,
+            egui::Align2::LEFT_BOTTOM,
+            text,
+            egui::FontId::monospace(12.0 * self.render_scale),
+            Color32::LIGHT_GRAY,
+        );
+    }
+
+    /// Index, mass and speed beside each visible body, offset past its circle.
+    fn draw_labels(&self, painter: &egui::Painter, rect: Rect) {
+        let font = egui::FontId::proportional(
+            (LABEL_FONT_SIZE * self.zoom.sqrt()).clamp(7.0, LABEL_FONT_SIZE) * self.render_scale,
+        );
+        let min_mass = self.label_min_mass.unwrap_or(f32::NEG_INFINITY);
+        for (i, body) in self.sim.bodies.iter().enumerate() {
+            if body.mass < min_mass {
+                continue;
+            }
+            let screen_pos = self.world_to_screen(body.pos, rect);
+            if !rect.contains(screen_pos) {
+                continue;
+            }
+            let offset = self.display_radius(body) * self.zoom + 4.0;
+            painter.text(
+                screen_pos + egui::vec2(offset, -offset),
+                egui::Align2::LEFT_BOTTOM,
+                format!("#{i} m={:.0} v={:.1}", body.mass, body.vel.norm()),
+                font.clone(),
+                Color32::LIGHT_GRAY,
+            );
+        }
+    }
+
+    /// A triangle on the edge of the view for each body outside it, pointing at the body
+    /// from the view center. Heavier bodies get bigger arrows, farther ones fainter.
+    fn draw_offscreen_arrows(&self, painter: &egui::Painter, rect: Rect) {
+        let heaviest = self.sim.bodies.iter().map(|b| b.mass).fold(0.0, f32::max);
+        if heaviest <= 0.0 {
+            return;
+        }
+        let min_mass = self.offscreen_min_mass.unwrap_or(f32::NEG_INFINITY);
+        let inner = rect.shrink(OFFSCREEN_ARROW_INSET);
+        let center = rect.center();
+        let half = inner.size() / 2.0;
+        for body in &self.sim.bodies {
+            if body.mass < min_mass {
+                continue;
+            }
+            let screen_pos = self.world_to_screen(body.pos, rect);
+            if rect.contains(screen_pos) || !screen_pos.is_finite() {
+                continue;
+            }
+            let offset = screen_pos - center;
+            let dir = offset.normalized();
+            // Walk from the center towards the body until the first side of the inset view.
+            let reach = (half.x / dir.x.abs()).min(half.y / dir.y.abs());
+            let tip = center + dir * reach;
+            let size =
+                OFFSCREEN_ARROW_SIZE * self.render_scale * (body.mass / heaviest).sqrt().max(0.3);
+            let opacity = (rect.size().length() / 2.0 / offset.length()).clamp(0.25, 1.0);
+            let back = tip - dir * size;
+            let side = dir.rot90() * (size * 0.4);
+            painter.add(egui::Shape::convex_polygon(
+                vec![tip, back + side, back - side],
+                body.color.gamma_multiply(opacity),
+                egui::Stroke::NONE,
+            ));
+        }
+    }
+
+    /// Seeded background stars of varying brightness, scrolling slowly with the camera
+    /// and wrapping around the view.
+    fn draw_starfield(&self, painter: &egui::Painter, rect: Rect) {
+        use rand::SeedableRng;
+
+        let count = (rect.area() / 10_000.0 * self.star_density) as usize;
+        let shift = egui::vec2(self.camera_pos.x, self.camera_pos.y) * STARFIELD_PARALLAX;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(STARFIELD_SEED);
+        for _ in 0..count {
+            let x = rng.random_range(0.0..rect.width()) - shift.x;
+            let y = rng.random_range(0.0..rect.height()) - shift.y;
+            let brightness: f32 = rng.random_range(0.15..1.0);
+            let pos =
+                rect.min + egui::vec2(x.rem_euclid(rect.width()), y.rem_euclid(rect.height()));
+            let gray = (brightness * 255.0) as u8;
+            painter.circle_filled(pos, 0.5 + brightness, Color32::from_gray(gray));
+        }
+    }
+
+    /// World-space grid at power-of-ten spacings picked from the zoom, with every tenth
+    /// line emphasized and labelled along the world axes (pinned to the view edge when
+    /// an axis is off screen).
+    fn draw_grid(&self, painter: &egui::Painter, rect: Rect) {
+        let exponent = (MIN_GRID_SPACING / self.zoom).log10().ceil() as i32;
+        let minor = 10f32.powi(exponent);
+        let decimals = (-exponent - 1).max(0) as usize;
+        let min = self.screen_to_world(rect.min, rect);
+        let max = self.screen_to_world(rect.max, rect);
+
+        let stroke_for = |k: i64| {
+            let gray = match k {
+                0 => 110,
+                k if k % 10 == 0 => 60,
+                _ => 25,
+            };
+            self.stroke(1.0, Color32::from_gray(gray))
+        };
+        let font = egui::FontId::monospace(10.0 * self.render_scale);
+        let label_color = Color32::from_gray(140);
+
+        let scale = self.render_scale;
+        let origin = self.world_to_screen(Vector2::zeros(), rect);
+        let label_y = origin
+            .y
+            .clamp(rect.top() + 2.0 * scale, rect.bottom() - 14.0 * scale);
+        let label_x = origin
+            .x
+            .clamp(rect.left() + 2.0 * scale, rect.right() - 40.0 * scale);
+
+        for k in (min.x / minor).floor() as i64..=(max.x / minor).ceil() as i64 {
+            let x = k as f32 * minor;
+            let sx = self.world_to_screen(Vector2::new(x, 0.0), rect).x;
+            let top = Pos2::new(sx, rect.top());
+            painter.line_segment([top, Pos2::new(sx, rect.bottom())], stroke_for(k));
+            if k % 10 == 0 {
+                let text = format!("{x:.decimals$}");
+                let pos = Pos2::new(sx + 2.0 * scale, label_y);
+                painter.text(pos, egui::Align2::LEFT_TOP, text, font.clone(), label_color);
+            }
+        }
+        for k in (min.y / minor).floor() as i64..=(max.y / minor).ceil() as i64 {
+            let y = k as f32 * minor;
+            let sy = self.world_to_screen(Vector2::new(0.0, y), rect).y;
+            let left = Pos2::new(rect.left(), sy);
+            painter.line_segment([left, Pos2::new(rect.right(), sy)], stroke_for(k));
+            if k % 10 == 0 {
+                let text = format!("{y:.decimals$}");
+                let pos = Pos2::new(label_x, sy + 2.0 * scale);
+                painter.text(pos, egui::Align2::LEFT_TOP, text, font.clone(), label_color);
+            }
+        }
+    }
+
+    /// Draws every body and the main view's frustum into the minimap; clicking or
+    /// dragging on it recenters the camera there.
+    fn minimap_ui(&mut self, response: &egui::Response, painter: &egui::Painter, view: Rect) {
+        let view_min = self.screen_to_world(view.min, view);
+        let view_max = self.screen_to_world(view.max, view);
+        let (mut min, mut max) = (view_min, view_max);
+        for body in &self.sim.bodies {
+            min = min.inf(&body.pos);
+            max = max.sup(&body.pos);
+        }
+        let map = MinimapTransform::fit(response.rect, min, max);
+
+        if (response.clicked() || response.dragged())
+            && let Some(pointer) = response.interact_pointer_pos()
+        {
+            let target = map.to_world(pointer);
+            self.camera_pos = Pos2::new(target.x, target.y);
+            self.release_camera();
+        }
+
+        painter.rect_filled(map.rect, 4.0, Color32::from_black_alpha(200));
+        painter.rect_stroke(
+            map.rect,
+            4.0,
+            egui::Stroke::new(1.0, Color32::DARK_GRAY),
+            egui::StrokeKind::Inside,
+        );
+        for body in &self.sim.bodies {
+            painter.circle_filled(map.to_screen(body.pos), 1.5, body.color);
+        }
+        painter.rect_stroke(
+            Rect::from_two_pos(map.to_screen(view_min), map.to_screen(view_max)),
+            0.0,
+            egui::Stroke::new(1.0, Color32::WHITE),
+            egui::StrokeKind::Middle,
+        );
+    }
+
+    /// Brings [`Self::prediction`] up to date for body `index`, integrating a fresh path on
+    /// copies of it and its attractors only when they, the step or the physics changed.
+    fn refresh_prediction(&mut self, index: usize) {
+        let Some(subject) = self.sim.bodies.get(index) else {
+            self.prediction = None;
+            return;
+        };
+        let bodies = self.prediction_bodies(subject, Some(index));
+        let dt = self.prediction_dt();
+        if let Some(prediction) = &self.prediction
+            && prediction.bodies == bodies
+            && prediction.dt == dt
+            && prediction.path.len() == self.prediction_steps + 1
+            && prediction.integrator == self.sim.integrator
+            && prediction.gravity == self.sim.gravity
+        {
+            return;
+        }
+        self.prediction = Some(Prediction {
+            path: self.integrate_path(bodies.clone()),
+            bodies,
+            dt,
+            integrator: self.sim.integrator,
+            gravity: self.sim.gravity,
+        });
+    }
+
+    /// A trail-less copy of `subject` followed by the [`PREDICTION_ATTRACTORS`] heaviest
+    /// bodies other than `exclude`, the only ones a prediction feels.
+    fn prediction_bodies(
+        &self,
+        subject: &CelestialBody,
+        exclude: Option<usize>,
+    ) -> Vec<CelestialBody> {
+        let mut attractors: Vec<&CelestialBody> = self
+            .sim
+            .bodies
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| Some(i) != exclude)
+            .map(|(_, b)| b)
+            .collect();
+        if attractors.len() > PREDICTION_ATTRACTORS {
+            attractors.select_nth_unstable_by(PREDICTION_ATTRACTORS - 1, |a, b| {
+                b.mass.total_cmp(&a.mass)
+            });
+            attractors.truncate(PREDICTION_ATTRACTORS);
+        }
+        std::iter::once(subject)
+            .chain(attractors)
+            .map(|b| CelestialBody {
+                trail: VecDeque::new(),
+                ..*b
+            })
+            .collect()
+    }
+
+    /// Positions of `bodies[0]` over `prediction_steps` steps of the bodies on their own.
+    fn integrate_path(&self, mut bodies: Vec<CelestialBody>) -> Vec<Vector2<f32>> {
+        let dt = self.prediction_dt();
+        let mut path = Vec::with_capacity(self.prediction_steps + 1);
+        path.push(bodies[0].pos);
+        for _ in 0..self.prediction_steps {
+            self.sim.integrator.step(&mut bodies, dt, &self.sim.gravity);
+            path.push(bodies[0].pos);
+        }
+        path
+    }
+
+    /// Step length for predictions: a fixed step at the current time scale, or at real
+    /// time while paused at zero.
+    fn prediction_dt(&self) -> f32 {
+        self.fixed_dt
+            * if self.time_scale > 0.0 {
+                self.time_scale
+            } else {
+                1.0
+            }
+    }
+
+    /// Starts searching for a launch from body `start` whose predicted path passes within
+    /// [`PLANNER_TOLERANCE`] of `target`. The simulation pauses while
+    /// [`Self::advance_probe_plan`] works through it over the next frames.
+    fn start_probe_plan(&mut self, start: usize, target: Vector2<f32>) {
+        self.cancel_probe_plan();
+        let Some(origin) = self.sim.bodies.get(start) else {
+            return;
+        };
+        let origin = CelestialBody {
+            trail: VecDeque::new(),
+            ..*origin
+        };
+        if self.prediction_steps == 0 {
+            self.file_status = Some("Probe planning needs prediction steps above 0".to_owned());
+            return;
+        }
+        // Fast enough to cover the distance within the prediction, up to well past escape.
+        let horizon = self.prediction_dt() * self.prediction_steps as f32;
+        let reach = ((target - origin.pos).norm() / horizon).max(f32::EPSILON);
+        let escape = (2.0 * self.sim.gravity.gravity_constant * origin.mass
+            / (origin.radius + PROBE_CLEARANCE))
+            .sqrt();
+        let (min_log, max_log) = ((reach * 0.25).ln(), (reach * 4.0 + escape * 2.0).ln());
+        let mut attractors = self.prediction_bodies(&origin, None);
+        attractors.remove(0);
+        self.probe_plan = Some(ProbePlan {
+            origin,
+            attractors,
+            target,
+            min_log_speed: min_log,
+            angle_step: std::f32::consts::TAU / PLANNER_ANGLES as f32,
+            speed_step: (max_log - min_log) / (PLANNER_SPEEDS - 1) as f32,
+            best: (f32::INFINITY, 0.0, min_log),
+            cells_done: 0,
+            refinements_done: 0,
+            resume: !self.paused,
+        });
+        self.paused = true;
+        self.file_status = None;
+    }
+
+    /// Spends up to [`PLANNER_FRAME_WORK`] on the probe search, launching the probe once
+    /// it is done.
+    fn advance_probe_plan(&mut self) {
+        let Some(mut plan) = self.probe_plan.take() else {
+            return;
+        };
+        let cost = self.prediction_steps * (plan.attractors.len() + 1).pow(2);
+        let mut budget = (PLANNER_FRAME_WORK / cost.max(1)).max(1);
+        while budget > 0 && !plan.finished() {
+            if plan.cells_done < PLANNER_ANGLES * PLANNER_SPEEDS {
+                let (a, s) = (
+                    plan.cells_done / PLANNER_SPEEDS,
+                    plan.cells_done % PLANNER_SPEEDS,
+                );
+                let angle = a as f32 * plan.angle_step;
+                let log_speed = plan.min_log_speed + s as f32 * plan.speed_step;
+                let distance = self.probe_miss(&plan, angle, log_speed);
+                if distance < plan.best.0 {
+                    plan.best = (distance, angle, log_speed);
+                }
+                plan.cells_done += 1;
+                budget -= 1;
+                continue;
+            }
+            let (best, angle, log_speed) = plan.best;
+            let better = [
+                (angle + plan.angle_step, log_speed),
+                (angle - plan.angle_step, log_speed),
+                (angle, log_speed + plan.speed_step),
+                (angle, log_speed - plan.speed_step),
+            ]
+            .into_iter()
+            .map(|(a, s)| (self.probe_miss(&plan, a, s), a, s))
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+            .filter(|candidate| candidate.0 < best);
+            match better {
+                Some(candidate) => plan.best = candidate,
+                None => {
+                    plan.angle_step /= 2.0;
+                    plan.speed_step /= 2.0;
+                }
+            }
+            plan.refinements_done += 1;
+            budget = budget.saturating_sub(4);
+        }
+        if !plan.finished() {
+            self.probe_plan = Some(plan);
+            return;
+        }
+
+        self.paused &= !plan.resume;
+        let (distance, angle, log_speed) = plan.best;
+        if distance > PLANNER_TOLERANCE {
+            self.file_status = Some(format!(
+                "No launch found; the closest path misses by {distance:.1}"
+            ));
+            return;
+        }
+        let Some(origin) = self.sim.bodies.iter().find(|b| b.id == plan.origin.id) else {
+            self.file_status = Some("The launching body is gone".to_owned());
+            return;
+        };
+        let probe = launch_probe(origin, angle, log_speed);
+        self.checkpoint();
+        if self.add_user_body(probe) {
+            self.selected = Some(self.sim.bodies.len() - 1);
+            self.file_status = Some(format!(
+                "Probe launched at speed {:.1}, passing within {distance:.1} of the target",
+                log_speed.exp()
+            ));
+        }
+    }
+
+    /// Closest the probe launched at `angle` and `log_speed` comes to the plan's target.
+    fn probe_miss(&self, plan: &ProbePlan, angle: f32, log_speed: f32) -> f32 {
+        let mut bodies = vec![launch_probe(&plan.origin, angle, log_speed)];
+        bodies.extend(plan.attractors.iter().cloned());
+        self.integrate_path(bodies)
+            .iter()
+            .map(|p| (p - plan.target).norm())
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    /// Drops any probe search under way, letting the simulation run again if it paused it.
+    fn cancel_probe_plan(&mut self) {
+        if let Some(plan) = self.probe_plan.take() {
+            self.paused &= !plan.resume;
+        }
+    }
+
+    /// Pins or releases body `index` as an undoable edit. A pinned body is stopped here
+    /// rather than at the next step, so its velocity reads zero straight away.
+    fn set_fixed(&mut self, index: usize, fixed: bool) {
+        self.checkpoint();
+        let body = &mut self.sim.bodies[index];
+        body.fixed = fixed;
+        if fixed {
+            body.vel = Vector2::zeros();
+        }
+    }
+
+    fn zero_velocities(&mut self) {
+        self.checkpoint();
+        for body in &mut self.sim.bodies {
+            body.vel = Vector2::zeros();
+        }
+    }
+
+    /// Stops every body, setting its velocity aside for [`Self::restore_velocities`].
+    fn freeze_velocities(&mut self) {
+        self.frozen_velocities = Some(self.sim.bodies.iter().map(|b| (b.id, b.vel)).collect());
+        self.zero_velocities();
+    }
+
+    /// Gives each body back the velocity it had when frozen, matched by id so merges,
+    /// removals and reordering in the meantime don't mix them up. Bodies that appeared
+    /// since, such as fragments and new additions, keep the velocity they have now.
+    fn restore_velocities(&mut self) {
+        let Some(frozen) = self.frozen_velocities.take() else {
+            return;
+        };
+        self.checkpoint();
+        for body in &mut self.sim.bodies {
+            if let Some(&vel) = frozen.get(&body.id) {
+                body.vel = vel;
+            }
+        }
+    }
+
+    /// Saves the current bodies so the next user edit can be undone.
+    fn checkpoint(&mut self) {
+        self.push_undo(self.sim.bodies.clone());
+    }
+
+    fn push_undo(&mut self, bodies: Vec<CelestialBody>) {
+        self.undo_stack.push_back(bodies);
+        if self.undo_stack.len() > UNDO_LIMIT {
+            self.undo_stack.pop_front();
+        }
+        self.redo_stack.clear();
+    }
+
+    fn undo(&mut self) {
+        if let Some(bodies) = self.undo_stack.pop_back() {
+            let current = self.sim.swap_bodies(bodies);
+            self.redo_stack.push(current);
+            self.forget_body_indices();
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(bodies) = self.redo_stack.pop() {
+            let current = self.sim.swap_bodies(bodies);
+            self.undo_stack.push_back(current);
+            self.forget_body_indices();
+        }
+    }
+
+    /// Drops every stored body index. A restored body list may be reordered as well as
+    /// shorter, so an index that is still in range can name a different body.
+    fn forget_body_indices(&mut self) {
+        self.selected = None;
+        self.follow = None;
+        self.camera_flight = None;
+        self.held = None;
+        self.context_target = None;
+        self.group.clear();
+        if let Some(comparison) = &mut self.comparison {
+            comparison.tracked.0 = None;
+        }
+    }
+
+    /// Puts the held body, if any, back under the pointer at rest.
+    fn pin_held(&mut self) {
+        if let Some(body) = self.held.and_then(|i| self.sim.bodies.get_mut(i)) {
+            body.pos = self.held_target;
+            body.vel = Vector2::zeros();
+        }
+    }
+
+    /// Points every stored body index at the same body after a removal pass.
+    fn apply_remap(&mut self, remap: &[Option<usize>]) {
+        self.selected = remap_index(self.selected, remap);
+        self.follow = remap_index(self.follow, remap);
+        if let Some(flight) = &mut self.camera_flight {
+            flight.body = remap_index(flight.body, remap);
+        }
+        if let Some(comparison) = &mut self.comparison {
+            comparison.tracked.0 = remap_index(comparison.tracked.0, remap);
+        }
+        self.frame_source.1 = remap_index(self.frame_source.1, remap);
+        self.context_target = remap_index(self.context_target, remap);
+        self.held = remap_index(self.held, remap);
+        self.group = self
+            .group
+            .iter()
+            .filter_map(|&i| remap_index(Some(i), remap))
+            .collect();
+    }
+
+    /// Removes body `index`, keeping stored indices pointing at the same bodies.
+    fn remove_body(&mut self, index: usize) {
+        self.checkpoint();
+        self.drop_bodies(&HashSet::from([index]));
+    }
+
+    /// Removes every body in `doomed` without taking a checkpoint, keeping stored indices
+    /// pointing at the same bodies.
+    fn drop_bodies(&mut self, doomed: &HashSet<usize>) {
+        let remap = self.sim.retain_indices(|i, _| !doomed.contains(&i));
+        self.apply_remap(&remap);
+    }
+
+    fn delete_group(&mut self) {
+        self.checkpoint();
+        let group = std::mem::take(&mut self.group);
+        self.drop_bodies(&group);
+    }
+
+    /// Folds every grouped body into the lowest-indexed one, conserving mass and momentum.
+    fn merge_group(&mut self) {
+        let mut members: Vec<usize> = self.group.iter().copied().collect();
+        members.sort_unstable();
+        let Some((&survivor, rest)) = members.split_first() else {
+            return;
+        };
+        if rest.is_empty() {
+            return;
+        }
+        self.checkpoint();
+        for &i in rest {
+            let other = self.sim.bodies[i].clone();
+            self.sim.bodies[survivor].absorb(&other, self.sim.radius_mode);
+        }
+        self.drop_bodies(&rest.iter().copied().collect());
+        // Bodies before the survivor are never removed, so its index is unchanged.
+        self.group = HashSet::from([survivor]);
+    }
+
+    /// Group operations for the bodies picked with the selection box.
+    fn group_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!("{} bodies selected", self.group.len()));
+        ui.horizontal(|ui| {
+            ui.label("Velocity offset");
+            ui.add(egui::DragValue::new(&mut self.group_velocity_offset.x).prefix("x: "));
+            ui.add(egui::DragValue::new(&mut self.group_velocity_offset.y).prefix("y: "));
+            if ui.button("Add Velocity").clicked() {
+                self.checkpoint();
+                for &i in &self.group {
+                    self.sim.bodies[i].vel += self.group_velocity_offset;
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Merge").clicked() {
+                self.merge_group();
+            }
+            if ui.button("Delete").clicked() {
+                self.delete_group();
+            }
+            if ui.button("Clear Selection").clicked() {
+                self.group.clear();
+            }
+        });
+    }
+
+    /// Scatters `ring_count` asteroids in an annulus around `center`, each on a roughly
+    /// circular orbit about whichever body pulls hardest at `center`.
+    fn spawn_ring(&mut self, center: Vector2<f32>) {
+        self.checkpoint();
+        let anchor = self
+            .dominant_body(center, None)
+            .map(|i| self.sim.bodies[i].clone());
+
+        let mut rng = rand::rng();
+        for _ in 0..self.ring_count {
+            let angle = rng.random_range(0.0..std::f32::consts::TAU);
+            let distance = rng.random_range(self.ring_radii.0..=self.ring_radii.1);
+            let pos = center + Vector2::new(angle.cos(), angle.sin()) * distance;
+            let mass = rng.random_range(self.ring_mass.0..=self.ring_mass.1);
+            let mut asteroid = CelestialBody::new(pos, mass, self.next_color());
+            if let Some(anchor) = &anchor {
+                asteroid.vel = anchor.vel
+                    + circular_orbit_velocity(
+                        anchor.mass,
+                        anchor.pos,
+                        pos,
+                        self.sim.gravity.gravity_constant,
+                    );
+            }
+            if !self.add_user_body(asteroid) {
+                break;
+            }
+        }
+    }
+
+    fn next_color(&mut self) -> Color32 {
+        let color = distinct_color(self.colors_assigned);
+        self.colors_assigned = self.colors_assigned.wrapping_add(1);
+        color
+    }
+
+    /// Adds a body the user asked for, respecting `max_bodies`. At the cap it recycles the
+    /// oldest disposable body if allowed, and otherwise refuses with a message. Returns
+    /// whether the body was added.
+    fn add_user_body(&mut self, body: CelestialBody) -> bool {
+        if let Some(cap) = self.max_bodies
+            && self.sim.bodies.len() >= cap
+        {
+            match self
+                .recycle_oldest
+                .then(|| self.oldest_disposable())
+                .flatten()
+            {
+                Some(index) => self.drop_bodies(&HashSet::from([index])),
+                None => {
+                    self.file_status = Some(format!("Body limit of {cap} reached"));
+                    return false;
+                }
+            }
+        }
+        self.sim.add_body(body);
+        true
+    }
+
+    /// The lowest-indexed, and so longest-lived, body that is light, free to move and not
+    /// picked or held by the user: an asteroid rather than a star or planet.
+    fn oldest_disposable(&self) -> Option<usize> {
+        let heaviest = self.sim.bodies.iter().map(|b| b.mass).fold(0.0, f32::max);
+        (0..self.sim.bodies.len()).find(|&i| {
+            let body = &self.sim.bodies[i];
+            !body.fixed
+                && body.mass < heaviest * RECYCLE_MASS_FRACTION
+                && self.selected != Some(i)
+                && self.follow != Some(i)
+                && self.held != Some(i)
+                && !self.group.contains(&i)
+        })
+    }
+
+    /// The body, other than `exclude`, whose pull is strongest at `pos`.
+    fn dominant_body(&self, pos: Vector2<f32>, exclude: Option<usize>) -> Option<usize> {
+        self.sim
+            .bodies
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| Some(i) != exclude)
+            .map(|(i, b)| (i, b.mass / (b.pos - pos).norm_squared().max(f32::EPSILON)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Puts body `index` on a circular orbit around its dominant neighbour.
+    fn make_circular(&mut self, index: usize) {
+        let pos = self.sim.bodies[index].pos;
+        let Some(central) = self.dominant_body(pos, Some(index)) else {
+            return;
+        };
+        self.checkpoint();
+        let central = &self.sim.bodies[central];
+        let vel = central.vel
+            + circular_orbit_velocity(
+                central.mass,
+                central.pos,
+                pos,
+                self.sim.gravity.gravity_constant,
+            );
+        self.sim.bodies[index].vel = vel;
+    }
+
+    /// Orbital elements of body `index` treating it and its dominant attractor as an
+    /// isolated pair, with the pair's combined mass as the gravitational parameter.
+    fn orbital_elements(&self, index: usize) -> Option<OrbitalElements> {
+        let body = &self.sim.bodies[index];
+        let attractor = self.dominant_body(body.pos, Some(index))?;
+        let central = &self.sim.bodies[attractor];
+        let mu = self.sim.gravity.gravity_constant * (central.mass + body.mass);
+        let r = body.pos - central.pos;
+        let v = body.vel - central.vel;
+        let (radius, speed) = (r.norm(), v.norm());
+        if radius == 0.0 || mu <= 0.0 {
+            return None;
+        }
+        let specific_energy = speed * speed / 2.0 - mu / radius;
+        let eccentricity_vector = (r * (speed * speed - mu / radius) - v * r.dot(&v)) / mu;
+        Some(OrbitalElements {
+            attractor,
+            radius,
+            speed,
+            specific_energy,
+            semi_major_axis: (specific_energy < 0.0).then(|| -mu / (2.0 * specific_energy)),
+            eccentricity: eccentricity_vector.norm(),
+        })
+    }
+
+    /// Distance from body `index` to the closest body heavier than it.
+    fn distance_to_nearest_heavier(&self, index: usize) -> Option<f32> {
+        let body = &self.sim.bodies[index];
+        self.sim
+            .bodies
+            .iter()
+            .filter(|other| other.mass > body.mass)
+            .map(|other| (other.pos - body.pos).norm())
+            .min_by(f32::total_cmp)
+    }
+
+    fn body_info_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(index) = self.selected.filter(|&i| i < self.sim.bodies.len()) else {
+            return;
+        };
+        ui.heading(format!("Body #{index}"));
+
+        let nearest = self.distance_to_nearest_heavier(index);
+        let elements = self.orbital_elements(index);
+        let radius_mode = self.sim.radius_mode;
+        let before = self.sim.bodies[index].clone();
+        let mut fixed = before.fixed;
+        let body = &mut self.sim.bodies[index];
+        egui::Grid::new("body_fields")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Mass");
+                if ui
+                    .add(egui::DragValue::new(&mut body.mass).range(0.01..=f32::MAX))
+                    .changed()
+                {
+                    body.resize(radius_mode);
+                }
+                ui.end_row();
+
+                ui.label("Density");
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut body.density)
+                            .range(1.0e-6..=f32::MAX)
+                            .speed(0.05),
+                    )
+                    .changed()
+                {
+                    body.resize(radius_mode);
+                }
+                ui.end_row();
+
+                ui.label("Radius");
+                ui.label(format!("{:.2}", body.radius));
+                ui.end_row();
+
+                ui.label("Shape");
+                egui::ComboBox::from_id_salt("body_shape")
+                    .selected_text(body.shape.label())
+                    .show_ui(ui, |ui| {
+                        for shape in BodyShape::ALL {
+                            ui.selectable_value(&mut body.shape, shape, shape.label());
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Repulsive");
+                ui.checkbox(&mut body.repulsive, "")
+                    .on_hover_text("Push other bodies away instead of pulling them");
+                ui.end_row();
+
+                ui.label("Fixed");
+                ui.checkbox(&mut fixed, "")
+                    .on_hover_text("Pin the body in place; it still pulls on the others");
+                ui.end_row();
+
+                ui.label("Position");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut body.pos.x).prefix("x: "));
+                    ui.add(egui::DragValue::new(&mut body.pos.y).prefix("y: "));
+                });
+                ui.end_row();
+
+                ui.label("Velocity");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut body.vel.x)
+                            .speed(0.1)
+                            .prefix("x: "),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut body.vel.y)
+                            .speed(0.1)
+                            .prefix("y: "),
+                    );
+                });
+                ui.end_row();
+
+                ui.label("Color");
+                ui.color_edit_button_srgba(&mut body.color);
+                ui.end_row();
+
+                ui.label("Speed");
+                ui.label(format!("{:.3}", body.vel.norm()));
+                ui.end_row();
+
+                ui.label("Nearest heavier body");
+                ui.label(nearest.map_or("none".to_owned(), |d| format!("{d:.1}")));
+                ui.end_row();
+
+                if let Some(orbit) = &elements {
+                    ui.label("Orbiting");
+                    ui.label(format!("#{}", orbit.attractor));
+                    ui.end_row();
+
+                    ui.label("Orbital radius");
+                    ui.label(format!("{:.1}", orbit.radius));
+                    ui.end_row();
+
+                    ui.label("Relative speed");
+                    ui.label(format!("{:.3}", orbit.speed));
+                    ui.end_row();
+
+                    ui.label("Specific energy");
+                    ui.label(format!("{:.3}", orbit.specific_energy));
+                    ui.end_row();
+
+                    ui.label("Semi-major axis");
+                    ui.label(
+                        orbit
+                            .semi_major_axis
+                            .map_or("unbound".to_owned(), |a| format!("{a:.1}")),
+                    );
+                    ui.end_row();
+
+                    ui.label("Eccentricity");
+                    ui.label(format!("{:.4}", orbit.eccentricity));
+                    ui.end_row();
+                }
+            });
+
+        // One snapshot per drag or typing session, not one per frame of it.
+        let changed = self.sim.bodies[index] != before;
+        if changed && !self.edit_in_progress {
+            let mut snapshot = self.sim.bodies.clone();
+            snapshot[index] = before;
+            self.push_undo(snapshot);
+        }
+        let still_editing = ui.input(|i| i.pointer.any_down()) || ui.ctx().wants_keyboard_input();
+        self.edit_in_progress = (self.edit_in_progress || changed) && still_editing;
+        if fixed != self.sim.bodies[index].fixed {
+            self.set_fixed(index, fixed);
+        }
+
+        ui.checkbox(&mut self.show_prediction, "Show predicted path");
+        ui.add(egui::Slider::new(&mut self.prediction_steps, 0..=5000).text("Prediction steps"));
+        ui.checkbox(&mut self.show_tides, "Show tidal force")
+            .on_hover_text("How the strongest pull differs across the body");
+        ui.horizontal(|ui| {
+            if ui
+                .button("Make Circular")
+                .on_hover_text("Orbit the body whose pull is strongest here")
+                .clicked()
+            {
+                self.make_circular(index);
+            }
+            if ui.button("Delete Body").clicked() {
+                self.remove_body(index);
+                self.selected = None;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.toggle_value(&mut self.planning_probe, "Plan Probe")
+                .on_hover_text("Search for a launch from this body that reaches a target");
+            if self.planning_probe {
+                ui.label("Click the target");
+            }
+        });
+    }
+
+    fn body_context_menu(&mut self, ui: &mut egui::Ui, index: usize) {
+        ui.label(format!("Body #{index}"));
+        ui.separator();
+        if ui.button("Select").clicked() {
+            self.selected = Some(index);
+            ui.close_menu();
+        }
+        if ui.button("Follow").clicked() {
+            self.selected = Some(index);
+            self.follow = Some(index);
+            self.lock_barycenter = false;
+            ui.close_menu();
+        }
+        let fixed = self.sim.bodies[index].fixed;
+        if ui
+            .button(if fixed { "Release" } else { "Make Fixed" })
+            .clicked()
+        {
+            self.set_fixed(index, !fixed);
+            ui.close_menu();
+        }
+        if ui.button("Make Circular Orbit").clicked() {
+            self.make_circular(index);
+            ui.close_menu();
+        }
+        if ui.button("Center Camera Here").clicked() {
+            let pos = self.sim.bodies[index].pos;
+            self.release_camera();
+            self.camera_pos = Pos2::new(pos.x, pos.y);
+            ui.close_menu();
+        }
+        if ui.button("Copy").clicked() {
+            self.clipboard = Some(self.sim.bodies[index].clone());
+            ui.close_menu();
+        }
+        if ui.button("Delete").clicked() {
+            self.remove_body(index);
+            ui.close_menu();
+        }
+    }
+
+    /// Menu for a right-click on empty space, acting at `context_point`.
+    fn background_context_menu(&mut self, ui: &mut egui::Ui) {
+        let point = self.context_point;
+        if ui.button("Add Body").clicked() {
+            self.checkpoint();
+            let color = self.next_color();
+            self.add_user_body(CelestialBody::new(point, self.new_body_mass, color));
+            ui.close_menu();
+        }
+        if ui
+            .add_enabled(self.clipboard.is_some(), egui::Button::new("Paste"))
+            .clicked()
+            && let Some(copied) = &self.clipboard
+        {
+            let body = CelestialBody {
+                pos: point,
+                trail: VecDeque::new(),
+                ..copied.clone()
+            };
+            self.checkpoint();
+            self.add_user_body(body);
+            ui.close_menu();
+        }
+        if ui.button("Center Camera Here").clicked() {
+            self.release_camera();
+            self.camera_pos = Pos2::new(point.x, point.y);
+            ui.close_menu();
+        }
+    }
+
+    /// Asks eframe for a capture of the window, to be written to `name` inside
+    /// `screenshot_dir` once it arrives a frame or so later.
+    fn request_screenshot(&self, ctx: &egui::Context, name: &str) {
+        let path = std::path::Path::new(&self.screenshot_dir).join(name);
+        ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(egui::UserData::new(path)));
+    }
+
+    /// Writes out every screenshot delivered since the last frame.
+    fn save_screenshots(&mut self, ctx: &egui::Context) {
+        let shots: Vec<_> = ctx.input(|i| {
+            i.raw
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    egui::Event::Screenshot {
+                        user_data, image, ..
+                    } => {
+                        let path = user_data
+                            .data
+                            .as_ref()?
+                            .downcast_ref::<std::path::PathBuf>()?;
+                        Some((path.clone(), image.clone()))
+                    }
+                    _ => None,
+                })
+                .collect()
+        });
+        for (path, image) in shots {
+            self.file_status = Some(match save_png(&path, &image) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(err) => format!("Screenshot failed: {err}"),
+            });
+        }
+    }
+
+    fn save_scene(&self, path: &str) -> std::io::Result<()> {
+        let scene = SavedScene {
+            bodies: self.sim.bodies.clone(),
+            camera_pos: self.camera_pos,
+            zoom: self.zoom,
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&scene)?)
+    }
+
+    /// Replaces the bodies and camera with the scene stored at `path`.
+    fn load_scene(&mut self, path: &str) -> std::io::Result<()> {
+        let scene: SavedScene = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        self.replace_bodies(scene.bodies);
+        self.camera_pos = scene.camera_pos;
+        self.zoom = scene.zoom;
+        self.pan_velocity = egui::Vec2::ZERO;
+        self.camera_flight = None;
+        Ok(())
+    }
+
+    /// Rebuilds the bodies of the current scenario and seed, keeping every setting.
+    fn load_scenario(&mut self) {
+        self.replace_bodies(self.scenario.bodies(self.seed, &self.scenario_params));
+        // Scenarios size their bodies for the area mode.
+        self.sim.set_radius_mode(self.sim.radius_mode);
+    }
+
+    /// Starts a new run with `bodies`, undoably. Selections, histories and everything else
+    /// about the old run go; settings and the camera stay.
+    fn replace_bodies(&mut self, bodies: Vec<CelestialBody>) {
+        self.cancel_probe_plan();
+        self.checkpoint();
+        self.sim.reset(bodies);
+        self.selected = None;
+        self.group.clear();
+        self.follow = None;
+        self.held = None;
+        self.create_drag_start = None;
+        self.box_select_start = None;
+        self.context_target = None;
+        self.measure = None;
+        self.measure_live = false;
+        self.frozen_velocities = None;
+        self.energy_history.clear();
+        self.diagnostics = None;
+        self.momentum_history.clear();
+        self.comparison = None;
+        self.ripples.clear();
+        self.recording.clear();
+        self.frame_history.clear();
+        self.frame_origin = None;
+        self.accumulator = 0.0;
+    }
+
+    /// Selects the next body, or the previous one, wrapping around the list, and has the
+    /// camera follow it.
+    fn cycle_selection(&mut self, forward: bool) {
+        let len = self.sim.bodies.len();
+        if len == 0 {
+            return;
+        }
+        let next = match self.selected {
+            Some(i) if forward => (i + 1) % len,
+            Some(i) => (i + len - 1) % len,
+            None if forward => 0,
+            None => len - 1,
+        };
+        self.selected = Some(next);
+        self.follow = Some(next);
+        self.lock_barycenter = false;
+    }
+
+    /// Carries the camera on with the speed of the last drag, decaying by `pan_friction`.
+    /// Stops at once if inertia is off or something else has taken the camera.
+    fn coast_camera(&mut self, dt: f32) {
+        if !self.pan_inertia || self.follow.is_some() || self.lock_barycenter {
+            self.pan_velocity = egui::Vec2::ZERO;
+        }
+        if self.pan_velocity.length() < PAN_REST_SPEED {
+            self.pan_velocity = egui::Vec2::ZERO;
+            return;
+        }
+        self.camera_pos -= self.pan_velocity * dt / self.zoom;
+        self.pan_velocity *= (-self.pan_friction * dt).exp();
+    }
+
+    /// Manual camera movement drops any follow or barycenter lock, and stops the coast
+    /// left over from an earlier drag and any flight under way.
+    fn release_camera(&mut self) {
+        self.follow = None;
+        self.pan_velocity = egui::Vec2::ZERO;
+        self.camera_flight = None;
+        self.lock_barycenter = false;
+    }
+
+    /// Centers the camera on the bounding box of every body, drawn radii included,
+    /// and zooms so the box fits in `rect` with [`FRAME_MARGIN`] to spare.
+    fn frame_all(&mut self, rect: Rect) {
+        let Some((center, zoom)) = self.frame_all_view(rect) else {
+            return;
+        };
+        self.release_camera();
+        self.camera_pos = Pos2::new(center.x, center.y);
+        self.zoom = zoom;
+    }
+
+    /// Camera position and zoom [`Self::frame_all`] would pick, if there are any bodies.
+    fn frame_all_view(&self, rect: Rect) -> Option<(Vector2<f32>, f32)> {
+        let (min, max) = self.bounds()?;
+        let size = (max - min).sup(&Vector2::repeat(MIN_FRAME_SIZE));
+        let usable = rect.size() * (1.0 - 2.0 * FRAME_MARGIN);
+        Some((
+            (min + max) / 2.0,
+            (usable.x / size.x).min(usable.y / size.y),
+        ))
+    }
+
+    /// Glides to body `index`, zooming until it is [`FOCUS_VIEW_FRACTION`] of the view across.
+    fn fly_to_body(&mut self, index: usize, rect: Rect) {
+        let body = &self.sim.bodies[index];
+        let diameter = 2.0 * self.display_radius(body);
+        let zoom = rect.size().min_elem() * FOCUS_VIEW_FRACTION / diameter.max(f32::EPSILON);
+        self.fly_to(Some(index), body.pos, zoom);
+    }
+
+    /// Starts a camera flight, dropping any follow or lock so they don't pull the other way.
+    fn fly_to(&mut self, body: Option<usize>, to: Vector2<f32>, zoom: f32) {
+        self.release_camera();
+        self.camera_flight = Some(CameraFlight {
+            body,
+            to,
+            from: self.camera_pos,
+            from_zoom: self.zoom,
+            to_zoom: zoom,
+            started: Instant::now(),
+        });
+    }
+
+    /// Moves the camera along the current flight, easing in and out. Position is blended
+    /// linearly and zoom geometrically, so zooming feels even over the whole flight.
+    /// Following a body or locking onto the barycenter takes over from a flight.
+    fn fly_camera(&mut self) {
+        if self.follow.is_some() || self.lock_barycenter {
+            self.camera_flight = None;
+        }
+        let Some(flight) = &mut self.camera_flight else {
+            return;
+        };
+        if let Some(body) = flight.body.and_then(|i| self.sim.bodies.get(i)) {
+            flight.to = body.pos;
+        }
+        let t = (flight.started.elapsed().as_secs_f32() / CAMERA_FLIGHT_DURATION).min(1.0);
+        let eased = t * t * (3.0 - 2.0 * t);
+        let to = Pos2::new(flight.to.x, flight.to.y);
+        self.camera_pos = flight.from.lerp(to, eased);
+        self.zoom = flight.from_zoom * (flight.to_zoom / flight.from_zoom).powf(eased);
+        if t >= 1.0 {
+            self.camera_flight = None;
+        }
+    }
+
+    /// Corners of the box holding every finite body, drawn radii included.
+    fn bounds(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        let mut min = Vector2::repeat(f32::INFINITY);
+        let mut max = Vector2::repeat(f32::NEG_INFINITY);
+        for body in self
+            .sim
+            .bodies
+            .iter()
+            .filter(|b| b.pos.iter().all(|x| x.is_finite()))
+        {
+            let r = Vector2::repeat(self.display_radius(body));
+            min = min.inf(&(body.pos - r));
+            max = max.sup(&(body.pos + r));
+        }
+        (min.x <= max.x).then_some((min, max))
+    }
+
+    /// The [`ExtentOverlay`] outline, labelled with its size.
+    fn draw_extent(&self, painter: &egui::Painter, rect: Rect) {
+        let stroke = self.stroke(1.0, Color32::from_rgb(150, 230, 150));
+        let font = egui::FontId::monospace(12.0 * self.render_scale);
+        match self.extent_overlay {
+            ExtentOverlay::Off => {}
+            ExtentOverlay::Box => {
+                let Some((min, max)) = self.bounds() else {
+                    return;
+                };
+                let (a, b) = (
+                    self.world_to_screen(min, rect),
+                    self.world_to_screen(max, rect),
+                );
+                painter.rect_stroke(
+                    Rect::from_two_pos(a, b),
+                    0.0,
+                    stroke,
+                    egui::StrokeKind::Middle,
+                );
+                let size = max - min;
+                painter.text(
+                    a,
+                    egui::Align2::LEFT_BOTTOM,
+                    format!("{:.0} x {:.0}", size.x, size.y),
+                    font,
+                    stroke.color,
+                );
+            }
+            ExtentOverlay::Circle => {
+                let center = self.sim.barycenter();
+                let Some(radius) = self
+                    .sim
+                    .bodies
+                    .iter()
+                    .map(|b| (b.pos - center).norm() + self.display_radius(b))
+                    .filter(|r| r.is_finite())
+                    .max_by(f32::total_cmp)
+                else {
+                    return;
+                };
+                let screen_center = self.world_to_screen(center, rect);
+                painter.circle_stroke(screen_center, radius * self.zoom, stroke);
+                painter.text(
+                    screen_center - egui::vec2(0.0, radius * self.zoom),
+                    egui::Align2::CENTER_BOTTOM,
+                    format!("r = {radius:.0}"),
+                    font,
+                    stroke.color,
+                );
+            }
+        }
+    }
+
+    /// Index of the body whose on-screen circle contains `screen_pos`, nearest first.
+    fn body_at(&self, screen_pos: Pos2, rect: Rect) -> Option<usize> {
+        self.sim
+            .bodies
+            .iter()
+            .enumerate()
+            .filter_map(|(i, body)| {
+                let dist = self.world_to_screen(body.pos, rect).distance(screen_pos);
+                let pick_radius = (self.display_radius(body) * self.zoom)
+                    .max(MIN_PICK_RADIUS * self.render_scale);
+                (dist <= pick_radius).then_some((i, dist))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// WASD / arrow keys pan at a constant on-screen speed, `+` / `-` zoom.
+    fn handle_keyboard_camera(&mut self, ui: &egui::Ui, rect: Rect) {
+        use egui::Key;
+
+        let (dt, pan, zoom_dir) = ui.input(|i| {
+            let axis = |neg: &[Key], pos: &[Key]| {
+                let down = |keys: &[Key]| keys.iter().any(|&k| i.key_down(k));
+                f32::from(down(pos)) - f32::from(down(neg))
+            };
+            let pan = egui::vec2(
+                axis(&[Key::A, Key::ArrowLeft], &[Key::D, Key::ArrowRight]),
+                axis(&[Key::W, Key::ArrowUp], &[Key::S, Key::ArrowDown]),
+            );
+            let zoom_dir = axis(&[Key::Minus], &[Key::Plus, Key::Equals]);
+            (i.unstable_dt, pan, zoom_dir)
+        });
+
+        if pan != egui::Vec2::ZERO {
+            self.camera_pos += pan * (KEY_PAN_SPEED * dt / self.zoom);
+            self.release_camera();
+        }
+        if zoom_dir != 0.0 {
+            self.zoom_at(1.0 + zoom_dir * KEY_ZOOM_RATE * dt, None, rect);
+        }
+    }
+
+    /// Pinch gestures zoom around their focus point and trackpad two-finger scrolling
+    /// pans. Mouse wheels keep zooming: those reporting in lines or pages always do, and
+    /// since browsers and macOS report them in points, so does any purely vertical scroll
+    /// outside a multi-touch gesture.
+    fn handle_scroll_and_gestures(&mut self, ui: &egui::Ui, rect: Rect) {
+        let (scroll, wheel_is_mouse, pinch, touch, hover) = ui.input(|i| {
+            let wheel_is_mouse = i.raw.events.iter().any(|e| {
+                matches!(
+                    e,
+                    egui::Event::MouseWheel {
+                        unit: egui::MouseWheelUnit::Line | egui::MouseWheelUnit::Page,
+                        ..
+                    }
+                )
+            });
+            (
+                i.raw_scroll_delta,
+                wheel_is_mouse,
+                i.zoom_delta(),
+                i.multi_touch(),
+                i.pointer.hover_pos(),
+            )
+        });
+
+        if pinch != 1.0 {
+            // Covers trackpad pinches, touchscreen pinches and ctrl + scroll alike.
+            let focus = touch.as_ref().map(|t| t.center_pos).or(hover);
+            self.zoom_at(pinch, focus, rect);
+        } else if wheel_is_mouse || (scroll.x == 0.0 && touch.is_none()) {
+            let zoom_factor = f32::max(0.1, 1.0 + scroll.y / 200.0);
+            if zoom_factor != 1.0 {
+                self.zoom_at(zoom_factor, hover, rect);
+            }
+        } else if scroll != egui::Vec2::ZERO {
+            self.camera_pos -= scroll / self.zoom;
+            self.release_camera();
+        }
+
+        if let Some(touch) = touch
+            && touch.translation_delta != egui::Vec2::ZERO
+        {
+            self.camera_pos -= touch.translation_delta / self.zoom;
+            self.release_camera();
+        }
+    }
+
+    /// Scales the zoom by `factor`, keeping the world point under `anchor` fixed on screen.
+    fn zoom_at(&mut self, factor: f32, anchor: Option<Pos2>, rect: Rect) {
+        self.camera_flight = None;
+        let Some(anchor) = anchor.filter(|p| rect.contains(*p)) else {
+            self.zoom *= factor;
+            return;
+        };
+        let world = self.screen_to_world(anchor, rect);
+        self.zoom *= factor;
+        let drift = self.world_to_screen(world, rect) - anchor;
+        self.camera_pos += drift / self.zoom;
+    }
+
+    /// Radius a body is drawn with, in world units.
+    fn display_radius(&self, body: &CelestialBody) -> f32 {
+        body.radius * self.radius_scale * self.render_scale
+    }
+
+    /// A line `width` points wide at a render scale of 1.
+    fn stroke(&self, width: f32, color: Color32) -> egui::Stroke {
+        egui::Stroke::new(width * self.render_scale, color)
+    }
+
+    fn world_to_screen(&self, world_pos: Vector2<f32>, rect: Rect) -> Pos2 {
+        let center = rect.center();
+        let screen_vec =
+            (world_pos - Vector2::new(self.camera_pos.x, self.camera_pos.y)) * self.zoom;
+        Pos2::new(center.x + screen_vec.x, center.y + screen_vec.y)
+    }
+
+    fn screen_to_world(&self, screen_pos: Pos2, rect: Rect) -> Vector2<f32> {
+        let offset = (screen_pos - rect.center()) / self.zoom;
+        Vector2::new(self.camera_pos.x + offset.x, self.camera_pos.y + offset.y)
+    }
+}
+
+/// Runs `steps` fixed steps of a scenario without a window and reports timing. With
+/// `report_probe`, also reports how the scenario's probe, if it has one, changed speed.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_bench(steps: u64, scenario: Scenario, seed: u64, report_probe: bool) {
+    let mut sim = SimState::new(scenario.bodies(seed, &ScenarioParams::default()));
+    let mut probe = scenario.probe().filter(|_| report_probe);
+    let speed_before = probe.map(|i| sim.bodies[i].vel.norm());
+    let start = Instant::now();
+    for _ in 0..steps {
+        if let Some(remap) = sim.step(FIXED_DT) {
+            probe = remap_index(probe, &remap);
+        }
+    }
+    let elapsed = start.elapsed();
+    if let Some(before) = speed_before {
+        match probe {
+            Some(i) => println!(
+                "probe speed {before:.2} before, {:.2} after",
+                sim.bodies[i].vel.norm()
+            ),
+            None => println!("probe speed {before:.2} before; it did not survive the run"),
+        }
+    }
+    let (_, _, total) = sim.total_energy();
+    println!(
+        "{steps} steps in {:.3}s ({:.1} us/step), {} bodies left, total energy {total:.4}",
+        elapsed.as_secs_f64(),
+        elapsed.as_secs_f64() * 1e6 / steps.max(1) as f64,
+        sim.bodies.len(),
+    );
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 fn main() {
+    let cli = match CliArgs::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("{err}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if cli.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    if let Some(steps) = cli.bench {
+        run_bench(
+            steps,
+            cli.scenario.unwrap_or(Scenario::AsteroidBelt),
+            cli.seed.unwrap_or(DEFAULT_SEED),
+            cli.report_probe,
+        );
+        return;
+    }
+
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default().with_inner_size([1500.0, 1200.0]),
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([cli.width, cli.height])
+            .with_title(&cli.title),
         ..Default::default()
     };
+    // The app name doubles as the storage key, so it stays fixed even when the title changes.
     eframe::run_native(
         "Interplanetary Simulation",
         options,
-        Box::new(|_cc| Ok(Box::<InterplanetarySimulation>::default())),
+        Box::new(move |cc| Ok(Box::new(InterplanetarySimulation::new(cc, &cli)))),
     )
     .unwrap();
 }
+
+/// Web entry point: mounts the app on the `the_canvas_id` canvas of `index.html`.
+/// There is no command line, so the app starts from its saved or default settings.
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    use eframe::wasm_bindgen::JsCast;
+    use eframe::web_sys;
+
+    let web_options = eframe::WebOptions::default();
+    wasm_bindgen_futures::spawn_local(async {
+        let canvas = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.get_element_by_id("the_canvas_id"))
+            .and_then(|element| element.dyn_into::<web_sys::HtmlCanvasElement>().ok())
+            .expect("index.html should contain a canvas with id `the_canvas_id`");
+        eframe::WebRunner::new()
+            .start(
+                canvas,
+                web_options,
+                Box::new(|cc| {
+                    Ok(Box::new(InterplanetarySimulation::new(
+                        cc,
+                        &CliArgs::default(),
+                    )))
+                }),
+            )
+            .await
+            .expect("failed to start eframe");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a fresh figure-eight for `frames` frames of `frame_dt` seconds each.
+    fn run_at(frame_dt: f32, frames: usize) -> InterplanetarySimulation {
+        let mut app = InterplanetarySimulation::from_scenario(
+            Scenario::FigureEight,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        );
+        // Binary fractions keep the accumulator exact, so both runs take the same steps.
+        app.fixed_dt = 1.0 / 128.0;
+        for _ in 0..frames {
+            app.advance(frame_dt);
+        }
+        app
+    }
+
+    #[test]
+    fn trajectories_do_not_depend_on_frame_rate() {
+        let slow = run_at(1.0 / 32.0, 64);
+        let fast = run_at(1.0 / 64.0, 128);
+        let mut reference =
+            SimState::new(Scenario::FigureEight.bodies(DEFAULT_SEED, &ScenarioParams::default()));
+        for _ in 0..256 {
+            reference.step(1.0 / 128.0);
+        }
+
+        assert_eq!(slow.sim.step_count, 256);
+        assert_eq!(fast.sim.step_count, 256);
+        assert!(slow.sim.bodies == reference.bodies);
+        assert!(fast.sim.bodies == reference.bodies);
+    }
+
+    #[test]
+    fn saved_scene_loads_back_identically() {
+        let saved = InterplanetarySimulation::from_scenario(
+            Scenario::SolarSystem,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        );
+        let path = std::env::temp_dir().join("interplanetary_round_trip.json");
+        let path = path.to_str().unwrap();
+        saved.save_scene(path).unwrap();
+
+        let mut loaded = InterplanetarySimulation::from_scenario(
+            Scenario::BinaryStars,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        );
+        loaded.load_scene(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert!(loaded.sim.bodies == saved.sim.bodies);
+        assert_eq!(loaded.camera_pos, saved.camera_pos);
+        assert_eq!(loaded.zoom, saved.zoom);
+    }
+
+    #[test]
+    fn switching_scenario_keeps_settings() {
+        let mut app = InterplanetarySimulation::from_scenario(
+            Scenario::FigureEight,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        );
+        app.sim.integrator = Integrator::Rk4;
+        app.time_scale = 3.0;
+        app.zoom = 0.25;
+        app.advance(0.1);
+        app.selected = Some(0);
+
+        app.scenario = Scenario::BinaryStars;
+        app.load_scenario();
+
+        let fresh = Scenario::BinaryStars.bodies(DEFAULT_SEED, &ScenarioParams::default());
+        assert_eq!(app.sim.bodies.len(), fresh.len());
+        assert_eq!(app.sim.step_count, 0);
+        assert_eq!(app.selected, None);
+        assert_eq!(app.sim.integrator, Integrator::Rk4);
+        assert_eq!(app.time_scale, 3.0);
+        assert_eq!(app.zoom, 0.25);
+    }
+
+    #[test]
+    fn world_to_screen_pins_the_camera_transform() {
+        let mut app = InterplanetarySimulation::from_scenario(
+            Scenario::FigureEight,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        );
+        app.camera_pos = Pos2::new(100.0, -50.0);
+        app.zoom = 2.0;
+        let rect = Rect::from_min_size(Pos2::new(10.0, 20.0), egui::vec2(800.0, 600.0));
+
+        assert_eq!(
+            app.world_to_screen(Vector2::new(100.0, -50.0), rect),
+            Pos2::new(410.0, 320.0)
+        );
+        assert_eq!(
+            app.world_to_screen(Vector2::new(130.0, -70.0), rect),
+            Pos2::new(470.0, 280.0)
+        );
+        let back = app.screen_to_world(Pos2::new(470.0, 280.0), rect);
+        assert_eq!(back, Vector2::new(130.0, -70.0));
+    }
+
+    #[test]
+    fn undo_forgets_selection_and_follow() {
+        let mut app = InterplanetarySimulation::from_scenario(
+            Scenario::FigureEight,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        );
+        app.checkpoint();
+        app.sim.bodies.swap(0, 2);
+        app.selected = Some(0);
+        app.follow = Some(0);
+
+        app.undo();
+
+        assert_eq!(app.selected, None);
+        assert_eq!(app.follow, None);
+    }
+
+    #[test]
+    fn prediction_is_capped_and_reused_until_something_moves() {
+        let mut app = InterplanetarySimulation::from_scenario(
+            Scenario::Cluster,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        );
+        app.prediction_steps = 50;
+        app.refresh_prediction(0);
+        let prediction = app.prediction.as_ref().unwrap();
+        assert_eq!(prediction.bodies.len(), PREDICTION_ATTRACTORS + 1);
+        assert_eq!(prediction.path.len(), 51);
+        let first = prediction.path.as_ptr();
+
+        app.refresh_prediction(0);
+        assert_eq!(app.prediction.as_ref().unwrap().path.as_ptr(), first);
+
+        app.sim.step(app.fixed_dt);
+        app.refresh_prediction(0);
+        let path = &app.prediction.as_ref().unwrap().path;
+        assert_eq!(path[0], app.sim.bodies[0].pos);
+    }
+
+    #[test]
+    fn fixing_a_body_stops_it_and_can_be_undone() {
+        let mut app = InterplanetarySimulation::from_scenario(
+            Scenario::FigureEight,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        );
+        let vel = app.sim.bodies[1].vel;
+        assert_ne!(vel, Vector2::zeros());
+
+        app.set_fixed(1, true);
+        assert!(app.sim.bodies[1].fixed);
+        assert_eq!(app.sim.bodies[1].vel, Vector2::zeros());
+
+        app.undo();
+        assert!(!app.sim.bodies[1].fixed);
+        assert_eq!(app.sim.bodies[1].vel, vel);
+    }
+
+    #[test]
+    fn restored_velocities_follow_bodies_through_edits() {
+        let mut app = InterplanetarySimulation::from_scenario(
+            Scenario::FigureEight,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        );
+        let frozen: Vec<_> = app.sim.bodies.iter().map(|b| b.vel).collect();
+        app.freeze_velocities();
+
+        app.remove_body(0);
+        app.add_user_body(CelestialBody::new(
+            Vector2::new(50.0, 50.0),
+            1.0,
+            Color32::WHITE,
+        ));
+        app.restore_velocities();
+
+        assert_eq!(app.sim.bodies[0].vel, frozen[1]);
+        assert_eq!(app.sim.bodies[1].vel, frozen[2]);
+        assert_eq!(app.sim.bodies[2].vel, Vector2::zeros());
+    }
+
+    #[test]
+    fn probe_planning_is_spread_over_frames() {
+        let mut app = InterplanetarySimulation::from_scenario(
+            Scenario::FigureEight,
+            DEFAULT_SEED,
+            ScenarioParams::default(),
+        );
+        let count = app.sim.bodies.len();
+        let target = app.sim.bodies[1].pos;
+        app.start_probe_plan(0, target);
+        assert!(app.paused);
+
+        app.advance_probe_plan();
+        assert!(app.probe_plan.is_some());
+        let mut frames = 1;
+        while app.probe_plan.is_some() {
+            app.advance_probe_plan();
+            frames += 1;
+            assert!(frames < 100);
+        }
+
+        assert!(!app.paused);
+        let status = app.file_status.as_deref().unwrap();
+        assert!(status.starts_with("Probe launched"), "{status}");
+        assert_eq!(app.sim.bodies.len(), count + 1);
+    }
+}
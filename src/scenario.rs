@@ -0,0 +1,428 @@
+use ecolor::Color32;
+use nalgebra::Vector2;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::CelestialBody;
+use crate::gravity::{G, circular_orbit_velocity};
+
+/// Total mass of the cluster, so that the default 500-unit disk falls in on itself
+/// within roughly twenty seconds whatever the body count.
+const CLUSTER_TOTAL_MASS: f32 = 5.0e9;
+/// Drawn radius of each cluster body; far smaller than its mass would give at the
+/// default density, so bodies only merge on close encounters.
+const CLUSTER_BODY_RADIUS: f32 = 2.0;
+
+/// World units per astronomical unit in [`PLANETS`]; keeps Neptune inside the default
+/// ejection distance.
+const AU: f32 = 80.0;
+/// Mass units per Earth mass in [`PLANETS`]. With the Sun at its real 333,000 Earth
+/// masses, a year takes about 30 seconds.
+pub const EARTH_MASS: f32 = 1000.0;
+
+/// One body on a circular orbit, as listed for [`circular_system`].
+pub struct OrbitSpec {
+    /// Orbit radius in world units.
+    pub semi_major_axis: f32,
+    pub mass: f32,
+    /// Drawn radius; real planets are far too small to see at this scale.
+    pub radius: f32,
+    pub color: Color32,
+}
+
+/// The eight planets with their real semi-major axes and masses, scaled by [`AU`] and
+/// [`EARTH_MASS`]. Add rows here to extend the system.
+const PLANETS: [OrbitSpec; 8] = [
+    planet(0.387, 0.0553, 2.0, Color32::from_rgb(170, 170, 170)), // Mercury
+    planet(0.723, 0.815, 3.0, Color32::from_rgb(230, 180, 80)),   // Venus
+    planet(1.000, 1.0, 3.0, Color32::from_rgb(0, 128, 255)),      // Earth
+    planet(1.524, 0.107, 2.5, Color32::from_rgb(200, 80, 40)),    // Mars
+    planet(5.203, 317.8, 8.0, Color32::from_rgb(210, 160, 110)),  // Jupiter
+    planet(9.537, 95.2, 7.0, Color32::from_rgb(230, 210, 150)),   // Saturn
+    planet(19.19, 14.5, 5.0, Color32::from_rgb(150, 220, 230)),   // Uranus
+    planet(30.07, 17.1, 5.0, Color32::from_rgb(80, 110, 230)),    // Neptune
+];
+
+/// A [`PLANETS`] row from astronomical units and Earth masses.
+const fn planet(au: f32, earth_masses: f32, radius: f32, color: Color32) -> OrbitSpec {
+    OrbitSpec {
+        semi_major_axis: au * AU,
+        mass: earth_masses * EARTH_MASS,
+        radius,
+        color,
+    }
+}
+
+/// Puts every body of `orbits` on a circular orbit around `central`, which comes first
+/// in the result. Starting angles are spread out so the bodies don't line up.
+pub fn circular_system(central: CelestialBody, orbits: &[OrbitSpec]) -> Vec<CelestialBody> {
+    let mut bodies = Vec::with_capacity(orbits.len() + 1);
+    for (k, spec) in orbits.iter().enumerate() {
+        let angle = k as f32 * 2.4;
+        let offset = Vector2::new(angle.cos(), angle.sin()) * spec.semi_major_axis;
+        let mut body = star(central.pos + offset, spec.mass, spec.radius, spec.color);
+        body.vel = central.vel + circular_orbit_velocity(central.mass, central.pos, body.pos, G);
+        bodies.push(body);
+    }
+    bodies.insert(0, central);
+    bodies
+}
+
+/// The probe comes right after the planet in [`slingshot`].
+const SLINGSHOT_PROBE: usize = 1;
+
+/// Center of every scenario. Scenarios are laid out in world units around it; only the
+/// camera maps them to pixels, so the window size never changes the dynamics.
+const ORIGIN: Vector2<f32> = Vector2::new(0.0, 0.0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scenario {
+    AsteroidBelt,
+    SolarSystem,
+    BinaryStars,
+    FigureEight,
+    Slingshot,
+    Cluster,
+    Planets,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 7] = [
+        Scenario::AsteroidBelt,
+        Scenario::SolarSystem,
+        Scenario::BinaryStars,
+        Scenario::FigureEight,
+        Scenario::Slingshot,
+        Scenario::Cluster,
+        Scenario::Planets,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Scenario::AsteroidBelt => "Asteroid belt",
+            Scenario::SolarSystem => "Solar system",
+            Scenario::BinaryStars => "Binary stars",
+            Scenario::FigureEight => "Figure-eight three-body",
+            Scenario::Slingshot => "Gravity-assist slingshot",
+            Scenario::Cluster => "Cluster collapse",
+            Scenario::Planets => "Planets (real ratios)",
+        }
+    }
+
+    /// Name used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Scenario::AsteroidBelt => "asteroid-belt",
+            Scenario::SolarSystem => "solar-system",
+            Scenario::BinaryStars => "binary-stars",
+            Scenario::FigureEight => "figure-eight",
+            Scenario::Slingshot => "slingshot",
+            Scenario::Cluster => "cluster",
+            Scenario::Planets => "planets",
+        }
+    }
+
+    /// Index of the scenario's probe, the light body whose speed tells the story.
+    pub fn probe(self) -> Option<usize> {
+        match self {
+            Scenario::Slingshot => Some(SLINGSHOT_PROBE),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Scenario> {
+        Scenario::ALL.into_iter().find(|s| s.name() == name)
+    }
+
+    /// Builds the scenario's bodies. The same `seed` and `params` always yield the same
+    /// bodies.
+    pub fn bodies(self, seed: u64, params: &ScenarioParams) -> Vec<CelestialBody> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        match self {
+            Scenario::AsteroidBelt => asteroid_belt(&mut rng, params),
+            Scenario::SolarSystem => solar_system(),
+            Scenario::BinaryStars => binary_stars(),
+            Scenario::FigureEight => figure_eight(),
+            Scenario::Slingshot => slingshot(),
+            Scenario::Cluster => cluster(&mut rng, params),
+            Scenario::Planets => circular_system(
+                star(ORIGIN, 333_000.0 * EARTH_MASS, 12.0, Color32::YELLOW),
+                &PLANETS,
+            ),
+        }
+    }
+}
+
+/// Which way the asteroids of a belt go around its central body.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrbitDirection {
+    /// Counter-clockwise on screen, the way the belt has always turned.
+    Prograde,
+    Retrograde,
+    /// Each asteroid picks a direction at random.
+    Mixed,
+}
+
+impl OrbitDirection {
+    pub const ALL: [OrbitDirection; 3] = [
+        OrbitDirection::Prograde,
+        OrbitDirection::Retrograde,
+        OrbitDirection::Mixed,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            OrbitDirection::Prograde => "Prograde",
+            OrbitDirection::Retrograde => "Retrograde",
+            OrbitDirection::Mixed => "Mixed",
+        }
+    }
+}
+
+/// Knobs for scenarios that generate many bodies. Scenarios ignore the ones that
+/// don't concern them.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScenarioParams {
+    pub belt_direction: OrbitDirection,
+    /// Eccentricity of each asteroid's orbit, from 0 (circular) up to just below 1.
+    pub belt_eccentricity: f32,
+    /// Exponent of the belt's mass distribution, dN/dm ∝ m^-alpha. Zero draws masses
+    /// uniformly; larger values give many small asteroids and a few large ones.
+    pub belt_mass_alpha: f32,
+    /// Lightest and heaviest asteroid of the belt.
+    pub belt_mass_range: (f32, f32),
+    /// Bodies in the collapsing cluster; they share a fixed total mass.
+    pub cluster_count: usize,
+    /// Radius of the disk the cluster starts in.
+    pub cluster_radius: f32,
+    /// Largest starting speed of a cluster body.
+    pub cluster_velocity_dispersion: f32,
+}
+
+impl Default for ScenarioParams {
+    fn default() -> Self {
+        Self {
+            belt_direction: OrbitDirection::Prograde,
+            belt_eccentricity: 0.0,
+            belt_mass_alpha: 0.0,
+            belt_mass_range: (1.0, 5.0),
+            cluster_count: 1000,
+            cluster_radius: 500.0,
+            cluster_velocity_dispersion: 2.0,
+        }
+    }
+}
+
+/// Stars are far heavier than the default density can draw sensibly, so they get
+/// an explicit radius instead, with the density that produces it.
+fn star(pos: Vector2<f32>, mass: f32, radius: f32, color: Color32) -> CelestialBody {
+    let mut body = CelestialBody::new(pos, mass, color);
+    body.radius = radius;
+    body.density = mass / (std::f32::consts::PI * radius * radius);
+    body
+}
+
+/// Draws a mass from `range` with density proportional to m^-alpha, by inverting the
+/// cumulative distribution.
+fn power_law_mass(rng: &mut StdRng, alpha: f32, (min, max): (f32, f32)) -> f32 {
+    let min = min.max(f32::MIN_POSITIVE);
+    let max = max.max(min);
+    let u: f32 = rng.random();
+    let k = 1.0 - alpha;
+    if k.abs() < 1e-3 {
+        // The integral of 1/m is a logarithm, so masses are uniform in log space.
+        min * (max / min).powf(u)
+    } else {
+        (min.powf(k) + u * (max.powf(k) - min.powf(k))).powf(1.0 / k)
+    }
+}
+
+/// Each asteroid starts at a random point of an ellipse with the belt's eccentricity,
+/// focused on the heaviest body, moving at the speed that orbit has there.
+fn asteroid_belt(rng: &mut StdRng, params: &ScenarioParams) -> Vec<CelestialBody> {
+    // Sun
+    let sun = CelestialBody::new(ORIGIN, 10000.0, Color32::YELLOW);
+    // Earth
+    let mut earth = CelestialBody::new(
+        ORIGIN + Vector2::new(100.0, 0.0),
+        100.0,
+        Color32::from_rgb(0, 128, 255),
+    );
+    earth.vel = sun.vel + circular_orbit_velocity(sun.mass, sun.pos, earth.pos, G);
+
+    let mut bodies = vec![sun, earth];
+    // The belt circles whichever body is heaviest, wherever it is and however it moves.
+    let central = bodies
+        .iter()
+        .max_by(|a, b| a.mass.total_cmp(&b.mass))
+        .expect("the belt needs a central body");
+    let (center, center_vel, central_mass) = (central.pos, central.vel, central.mass);
+    // Asteroids
+    for _ in 0..200 {
+        let angle = rng.random_range(0.0..std::f32::consts::TAU);
+        let distance = rng.random_range(150.0..350.0);
+        let pos = center + Vector2::new(distance * angle.cos(), distance * angle.sin());
+        let mass = power_law_mass(rng, params.belt_mass_alpha, params.belt_mass_range);
+        let color = Color32::GRAY;
+        let mut asteroid = CelestialBody::new(pos, mass, color);
+
+        let to_center = center - pos;
+        let radial = -to_center.normalize();
+        let mut tangential = Vector2::new(-to_center.y, to_center.x).normalize();
+        let retrograde = match params.belt_direction {
+            OrbitDirection::Prograde => false,
+            OrbitDirection::Retrograde => true,
+            OrbitDirection::Mixed => rng.random_bool(0.5),
+        };
+        if retrograde {
+            tangential = -tangential;
+        }
+        let speed = circular_orbit_velocity(central_mass, center, pos, G).norm();
+
+        // On an orbit of eccentricity e at true anomaly ν, the radial and tangential
+        // speeds are e·sin ν and 1 + e·cos ν times sqrt(GM/p), and sqrt(GM/p) is the
+        // circular speed at this distance over sqrt(1 + e·cos ν).
+        let e = params.belt_eccentricity.clamp(0.0, 0.99);
+        let anomaly = if e > 0.0 {
+            rng.random_range(0.0..std::f32::consts::TAU)
+        } else {
+            0.0
+        };
+        let scale = speed / (1.0 + e * anomaly.cos()).sqrt();
+        asteroid.vel = center_vel
+            + tangential * (scale * (1.0 + e * anomaly.cos()))
+            + radial * (scale * e * anomaly.sin());
+
+        bodies.push(asteroid);
+    }
+    bodies
+}
+
+fn solar_system() -> Vec<CelestialBody> {
+    // Heavy enough that the inner planets orbit in tens of seconds.
+    let sun = star(ORIGIN, 4.0e10, 40.0, Color32::YELLOW);
+    let planets = [
+        (170.0, 120.0, Color32::from_rgb(170, 170, 170)), // Mercury
+        (230.0, 400.0, Color32::from_rgb(230, 180, 80)),  // Venus
+        (300.0, 500.0, Color32::from_rgb(0, 128, 255)),   // Earth
+        (380.0, 150.0, Color32::from_rgb(200, 80, 40)),   // Mars
+        (560.0, 5000.0, Color32::from_rgb(210, 160, 110)), // Jupiter
+        (760.0, 3000.0, Color32::from_rgb(230, 210, 150)), // Saturn
+    ];
+
+    let mut bodies = Vec::with_capacity(planets.len() + 1);
+    for (k, &(distance, mass, color)) in planets.iter().enumerate() {
+        // Spread the starting angles so the planets don't line up.
+        let angle = k as f32 * 2.4;
+        let offset = Vector2::new(angle.cos(), angle.sin()) * distance;
+        let mut planet = CelestialBody::new(sun.pos + offset, mass, color);
+        planet.vel = circular_orbit_velocity(sun.mass, sun.pos, planet.pos, G);
+        bodies.push(planet);
+    }
+    bodies.insert(0, sun);
+    bodies
+}
+
+fn binary_stars() -> Vec<CelestialBody> {
+    let mass = 2.0e10;
+    let separation = 300.0;
+    // Each star circles the barycenter at half the separation.
+    let speed = (G * mass / (2.0 * separation)).sqrt();
+    let half = Vector2::new(separation / 2.0, 0.0);
+
+    let mut a = star(ORIGIN - half, mass, 20.0, Color32::from_rgb(255, 200, 120));
+    let mut b = star(ORIGIN + half, mass, 20.0, Color32::from_rgb(150, 180, 255));
+    a.vel = Vector2::new(0.0, -speed);
+    b.vel = Vector2::new(0.0, speed);
+    vec![a, b]
+}
+
+/// Chenciner & Montgomery's figure-eight choreography, scaled from G = m = 1 units.
+fn figure_eight() -> Vec<CelestialBody> {
+    let length = 100.0;
+    let mass = 4.0e9;
+    let speed = (G * mass / length).sqrt();
+
+    let x1 = Vector2::new(0.970_004_4, -0.243_087_5);
+    let v3 = Vector2::new(-0.932_407_4, -0.864_731_5);
+    let initial = [(x1, -v3 / 2.0), (-x1, -v3 / 2.0), (Vector2::zeros(), v3)];
+    let colors = [
+        Color32::from_rgb(255, 90, 90),
+        Color32::from_rgb(90, 255, 120),
+        Color32::from_rgb(100, 150, 255),
+    ];
+
+    initial
+        .iter()
+        .zip(colors)
+        .map(|(&(pos, vel), color)| {
+            let mut body = star(ORIGIN + pos * length, mass, 8.0, color);
+            body.vel = vel * speed;
+            body
+        })
+        .collect()
+}
+
+/// A probe crosses just behind a planet moving at 60 units/s. In the planet's frame the
+/// flyby only turns the probe's velocity, but turned towards the planet's motion it
+/// leaves roughly twice as fast as it came, from 60 to about 124 units/s, passing
+/// about 26 units from the planet's center.
+fn slingshot() -> Vec<CelestialBody> {
+    let mut planet = star(
+        ORIGIN + Vector2::new(-400.0, 0.0),
+        4.0e9,
+        10.0,
+        Color32::from_rgb(210, 160, 110),
+    );
+    planet.vel = Vector2::new(60.0, 0.0);
+
+    // Aimed so the planet is still 170 units short of the crossing point when the probe
+    // gets there, which puts the probe on its trailing side.
+    let mut probe = CelestialBody::new(ORIGIN + Vector2::new(-170.0, -300.0), 1.0, Color32::WHITE);
+    probe.vel = Vector2::new(0.0, 60.0);
+    vec![planet, probe]
+}
+
+/// Equal-mass bodies scattered uniformly over a disk, nearly at rest, so the whole cloud
+/// falls inwards, clumps and settles. Large counts go through Barnes-Hut.
+fn cluster(rng: &mut StdRng, params: &ScenarioParams) -> Vec<CelestialBody> {
+    let count = params.cluster_count.max(1);
+    let mass = CLUSTER_TOTAL_MASS / count as f32;
+    // The square root spreads points evenly over the disk's area instead of bunching
+    // them at the center.
+    let mut in_disk = |radius: f32| {
+        let angle = rng.random_range(0.0..std::f32::consts::TAU);
+        let distance = radius * rng.random_range(0.0f32..1.0).sqrt();
+        Vector2::new(angle.cos(), angle.sin()) * distance
+    };
+    (0..count)
+        .map(|_| {
+            let pos = ORIGIN + in_disk(params.cluster_radius);
+            let mut body = star(
+                pos,
+                mass,
+                CLUSTER_BODY_RADIUS,
+                Color32::from_rgb(200, 210, 255),
+            );
+            body.vel = in_disk(params.cluster_velocity_dispersion);
+            body
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_builds_the_same_belt() {
+        let params = ScenarioParams::default();
+        let a = Scenario::AsteroidBelt.bodies(7, &params);
+        let b = Scenario::AsteroidBelt.bodies(7, &params);
+        let other = Scenario::AsteroidBelt.bodies(8, &params);
+        assert!(a == b);
+        assert!(a != other);
+    }
+}
@@ -0,0 +1,1057 @@
+//! The physics half of the simulation: everything a step needs, with no UI state.
+
+use std::collections::VecDeque;
+
+use nalgebra::Vector2;
+use serde::{Deserialize, Serialize};
+
+use crate::spatial_hash::SpatialHash;
+use crate::{CelestialBody, GravitySettings, Integrator, RadiusMode};
+
+/// Distance from the barycenter beyond which an escaping body counts as ejected.
+const DEFAULT_EJECTION_DISTANCE: f32 = 3000.0;
+/// Oldest entries are dropped from the event log past this many.
+const EVENT_LOG_LEN: usize = 500;
+/// Default half-width and half-height of the world box used by [`Boundary`].
+const DEFAULT_BOUNDARY_HALF_EXTENT: f32 = 1000.0;
+/// Positions kept per trail until the user changes it.
+const DEFAULT_TRAIL_LENGTH: usize = 200;
+/// Fragments start this many times further apart than it takes for them to just touch.
+const FRAGMENT_SPACING: f32 = 1.05;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CollisionMode {
+    PassThrough,
+    Merge,
+    Elastic,
+    /// Gentle impacts merge; ones faster than [`Fragmentation::min_speed`] shatter.
+    Fragment,
+}
+
+impl CollisionMode {
+    pub const ALL: [CollisionMode; 4] = [
+        CollisionMode::PassThrough,
+        CollisionMode::Merge,
+        CollisionMode::Elastic,
+        CollisionMode::Fragment,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CollisionMode::PassThrough => "Pass through",
+            CollisionMode::Merge => "Merge",
+            CollisionMode::Elastic => "Elastic",
+            CollisionMode::Fragment => "Fragment",
+        }
+    }
+}
+
+/// When and how finely [`CollisionMode::Fragment`] shatters colliding bodies.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fragmentation {
+    /// Impact speeds below this merge the pair instead.
+    pub min_speed: f32,
+    /// Most pieces a single impact produces.
+    pub max_pieces: usize,
+    /// No piece is lighter than this, so light pairs break into fewer pieces or merge.
+    pub min_mass: f32,
+}
+
+impl Default for Fragmentation {
+    fn default() -> Self {
+        Self {
+            min_speed: 100.0,
+            max_pieces: 8,
+            min_mass: 1.0,
+        }
+    }
+}
+
+/// What happens to bodies reaching the edge of the world box.
+///
+/// Only positions and velocities see the box. Gravity still acts across it directly,
+/// so under [`Boundary::Wrap`] a body near one edge doesn't feel bodies just across
+/// the opposite edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Boundary {
+    /// No box; space goes on forever.
+    Open,
+    /// Leaving through one side re-enters through the opposite one.
+    Wrap,
+    /// The walls bounce bodies back without losing speed.
+    Reflect,
+}
+
+impl Boundary {
+    pub const ALL: [Boundary; 3] = [Boundary::Open, Boundary::Wrap, Boundary::Reflect];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Boundary::Open => "Open",
+            Boundary::Wrap => "Wrap around",
+            Boundary::Reflect => "Reflect",
+        }
+    }
+}
+
+/// Brings `x` back into `min..max` by whole periods of the box.
+fn wrap_coordinate(x: f32, min: f32, max: f32) -> f32 {
+    min + (x - min).rem_euclid(max - min)
+}
+
+/// Mirrors a coordinate that went past a wall back inside, turning its velocity
+/// component away from that wall. Returns the new `(position, velocity)`.
+fn reflect_coordinate(x: f32, v: f32, min: f32, max: f32) -> (f32, f32) {
+    if x < min {
+        ((2.0 * min - x).min(max), v.abs())
+    } else if x > max {
+        ((2.0 * max - x).max(min), -v.abs())
+    } else {
+        (x, v)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SimEventKind {
+    /// The second body was absorbed into the first.
+    Merge {
+        absorbed_mass: f32,
+        resulting_mass: f32,
+        /// Where the merged body ended up.
+        position: Vector2<f32>,
+    },
+    /// The pair collided fast enough to shatter into this many equal pieces.
+    Fragmentation {
+        pieces: usize,
+        /// Where the pair's center of mass was at the impact.
+        position: Vector2<f32>,
+    },
+    /// An elastic bounce.
+    Collision,
+    /// The pair started overlapping while collisions pass through.
+    ClosePass,
+    /// The body is leaving the system for good.
+    Ejection,
+    /// The body's position or velocity stopped being finite, so it was removed.
+    Diverged,
+}
+
+/// Something notable that happened during a physics step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimEvent {
+    /// Simulation time of the step, in seconds.
+    pub time: f32,
+    /// Indices of the bodies involved, as they were when the event happened.
+    pub bodies: Vec<usize>,
+    pub kind: SimEventKind,
+}
+
+impl SimEvent {
+    pub fn describe(&self) -> String {
+        let a = self.bodies[0];
+        let b = self.bodies.get(1).copied().unwrap_or(a);
+        let what = match self.kind {
+            SimEventKind::Merge {
+                absorbed_mass,
+                resulting_mass,
+                ..
+            } => format!("#{a} absorbed #{b} (+{absorbed_mass:.1}, now {resulting_mass:.1})"),
+            SimEventKind::Fragmentation { pieces, .. } => {
+                format!("#{a} and #{b} shattered into {pieces} pieces")
+            }
+            SimEventKind::Collision => format!("#{a} and #{b} collided"),
+            SimEventKind::ClosePass => format!("#{a} and #{b} passed through each other"),
+            SimEventKind::Ejection => format!("#{a} was ejected"),
+            SimEventKind::Diverged => format!("#{a} diverged and was removed"),
+        };
+        format!("[{:.2}s] {what}", self.time)
+    }
+}
+
+/// Every overlapping pair as `[lower, higher]` index, in ascending order.
+fn overlapping_pairs(bodies: &[CelestialBody]) -> Vec<[usize; 2]> {
+    SpatialHash::build(bodies)
+        .candidate_pairs()
+        .into_iter()
+        .filter(|&[i, j]| bodies[i].overlaps(&bodies[j]))
+        .collect()
+}
+
+/// Bounces every overlapping, approaching pair with an impulse along the line of
+/// centers, then pushes the pair apart so it doesn't stay interpenetrated.
+///
+/// `restitution` of 1.0 conserves kinetic energy; 0.0 kills all normal relative velocity.
+/// `friction` is a Coulomb coefficient: a glancing impact also gets a tangential impulse
+/// against the sliding velocity of up to `friction` times the normal impulse, never more
+/// than it takes to stop the sliding. Head-on impacts have no sliding velocity, so it
+/// leaves them alone. Overlaps caused by this pass's own push-apart are left for the
+/// next step.
+fn resolve_elastic_collisions(
+    bodies: &mut [CelestialBody],
+    restitution: f32,
+    friction: f32,
+    time: f32,
+    events: &mut Vec<SimEvent>,
+) {
+    let mut pairs = SpatialHash::build(bodies).candidate_pairs();
+    // Same order as walking every `j` and then every `i < j`.
+    pairs.sort_unstable_by_key(|&[i, j]| (j, i));
+    for [i, j] in pairs {
+        let (head, tail) = bodies.split_at_mut(j);
+        let (a, b) = (&mut head[i], &mut tail[0]);
+        if !a.overlaps(b) {
+            continue;
+        }
+        let delta = b.pos - a.pos;
+        let dist = delta.norm();
+        let normal = if dist > 0.0 {
+            delta / dist
+        } else {
+            Vector2::x()
+        };
+
+        // Fixed bodies behave as if infinitely heavy.
+        let inv_a = if a.fixed { 0.0 } else { 1.0 / a.mass };
+        let inv_b = if b.fixed { 0.0 } else { 1.0 / b.mass };
+        if inv_a + inv_b == 0.0 {
+            continue;
+        }
+        let approach = (b.vel - a.vel).dot(&normal);
+        if approach < 0.0 {
+            let impulse = -(1.0 + restitution) * approach / (inv_a + inv_b);
+            let sliding = (b.vel - a.vel) - normal * approach;
+            let slide_speed = sliding.norm();
+            let mut kick = normal * impulse;
+            if slide_speed > 0.0 {
+                let tangential = (friction * impulse).min(slide_speed / (inv_a + inv_b));
+                kick -= sliding / slide_speed * tangential;
+            }
+            a.vel -= kick * inv_a;
+            b.vel += kick * inv_b;
+            events.push(SimEvent {
+                time,
+                bodies: vec![i, j],
+                kind: SimEventKind::Collision,
+            });
+        }
+
+        let overlap = a.radius + b.radius - dist;
+        let share = overlap / (inv_a + inv_b);
+        a.pos -= normal * (share * inv_a);
+        b.pos += normal * (share * inv_b);
+    }
+}
+
+/// Merges every overlapping pair. A body absorbed this pass never takes part in another merge,
+/// and overlaps that only appear once a merged body has grown are left for the next step.
+///
+/// Returns the new index of every old body, or `None` for bodies that were absorbed.
+fn merge_collisions(
+    bodies: &mut Vec<CelestialBody>,
+    radius_mode: RadiusMode,
+    time: f32,
+    events: &mut Vec<SimEvent>,
+) -> Vec<Option<usize>> {
+    let mut absorbed = vec![false; bodies.len()];
+    for [i, j] in SpatialHash::build(bodies).candidate_pairs() {
+        if absorbed[i] || absorbed[j] || !bodies[i].overlaps(&bodies[j]) {
+            continue;
+        }
+        let (head, tail) = bodies.split_at_mut(j);
+        head[i].absorb(&tail[0], radius_mode);
+        absorbed[j] = true;
+        events.push(SimEvent {
+            time,
+            bodies: vec![i, j],
+            kind: SimEventKind::Merge {
+                absorbed_mass: tail[0].mass,
+                resulting_mass: head[i].mass,
+                position: head[i].pos,
+            },
+        });
+    }
+
+    retain_with_remap(bodies, |i, _| !absorbed[i])
+}
+
+/// Like [`merge_collisions`], except that a pair meeting faster than
+/// [`Fragmentation::min_speed`] is merged and then split into equal pieces on a ring
+/// around the merged center. The pieces fly straight out from it, all at one speed, so
+/// mass, momentum and the center of mass are unchanged. They carry away `restitution`
+/// of the kinetic energy the pair had relative to its center of mass. Fixed bodies never
+/// shatter. A shattered body takes part in no other collision this pass.
+///
+/// The first piece keeps the index of the pair's lower body and the rest are appended.
+/// Returns the new index of every old body, or `None` for bodies that were absorbed.
+fn fragment_collisions(
+    bodies: &mut Vec<CelestialBody>,
+    radius_mode: RadiusMode,
+    restitution: f32,
+    fragmentation: &Fragmentation,
+    time: f32,
+    events: &mut Vec<SimEvent>,
+) -> Vec<Option<usize>> {
+    let mut absorbed = vec![false; bodies.len()];
+    let mut shattered = vec![false; bodies.len()];
+    let mut debris = Vec::new();
+    for [i, j] in SpatialHash::build(bodies).candidate_pairs() {
+        if absorbed[i] || absorbed[j] || shattered[i] || shattered[j] {
+            continue;
+        }
+        if !bodies[i].overlaps(&bodies[j]) {
+            continue;
+        }
+        let (head, tail) = bodies.split_at_mut(j);
+        let (a, b) = (&mut head[i], &tail[0]);
+        let delta = b.pos - a.pos;
+        let impact = (b.vel - a.vel).norm();
+        let reduced_mass = a.mass * b.mass / (a.mass + b.mass);
+        let base_angle = delta.y.atan2(delta.x);
+        a.absorb(b, radius_mode);
+        absorbed[j] = true;
+
+        let pieces = ((a.mass / fragmentation.min_mass.max(f32::MIN_POSITIVE)) as usize)
+            .min(fragmentation.max_pieces);
+        if a.fixed || impact < fragmentation.min_speed || pieces < 2 {
+            events.push(SimEvent {
+                time,
+                bodies: vec![i, j],
+                kind: SimEventKind::Merge {
+                    absorbed_mass: b.mass,
+                    resulting_mass: a.mass,
+                    position: a.pos,
+                },
+            });
+            continue;
+        }
+
+        // Equal pieces at equal speeds: kinetic energy M·u²/2 = restitution · μ·v²/2.
+        let speed = impact * (restitution * reduced_mass / a.mass).sqrt();
+        let mut piece = a.clone();
+        piece.mass = a.mass / pieces as f32;
+        piece.trail.clear();
+        piece.resize(radius_mode);
+        // Neighbors on a ring of radius d sit 2·d·sin(π/n) apart; that must clear two radii.
+        let ring = piece.radius / (std::f32::consts::PI / pieces as f32).sin() * FRAGMENT_SPACING;
+        let (center, center_vel) = (a.pos, a.vel);
+        for k in 0..pieces {
+            let angle = base_angle + std::f32::consts::TAU * k as f32 / pieces as f32;
+            let dir = Vector2::new(angle.cos(), angle.sin());
+            let mut fragment = piece.clone();
+            fragment.pos = center + dir * ring;
+            fragment.vel = center_vel + dir * speed;
+            if k == 0 {
+                fragment.trail = std::mem::take(&mut a.trail);
+                *a = fragment;
+            } else {
+                debris.push(fragment);
+            }
+        }
+        shattered[i] = true;
+        events.push(SimEvent {
+            time,
+            bodies: vec![i, j],
+            kind: SimEventKind::Fragmentation {
+                pieces,
+                position: center,
+            },
+        });
+    }
+
+    let remap = retain_with_remap(bodies, |i, _| !absorbed[i]);
+    bodies.extend(debris);
+    remap
+}
+
+/// Keeps the bodies `keep` accepts, given each one's index, and returns the new index of
+/// every old body, or `None` for the ones dropped.
+fn retain_with_remap(
+    bodies: &mut Vec<CelestialBody>,
+    keep: impl Fn(usize, &CelestialBody) -> bool,
+) -> Vec<Option<usize>> {
+    let mut next = 0;
+    let remap: Vec<_> = bodies
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            keep(i, b).then(|| {
+                next += 1;
+                next - 1
+            })
+        })
+        .collect();
+    let mut kept = remap.iter();
+    bodies.retain(|_| kept.next().unwrap().is_some());
+    remap
+}
+
+/// Chains an optional earlier remap with a later one.
+fn compose_remaps(
+    first: Option<Vec<Option<usize>>>,
+    then: Vec<Option<usize>>,
+) -> Vec<Option<usize>> {
+    match first {
+        Some(first) => first.iter().map(|&i| remap_index(i, &then)).collect(),
+        None => then,
+    }
+}
+
+/// Follows a body index through a removal pass described by `remap`.
+pub fn remap_index(index: Option<usize>, remap: &[Option<usize>]) -> Option<usize> {
+    index.and_then(|i| remap.get(i).copied().flatten())
+}
+
+/// Bodies plus every setting that affects how they evolve.
+#[derive(Clone)]
+pub struct SimState {
+    pub bodies: Vec<CelestialBody>,
+    pub integrator: Integrator,
+    pub gravity: GravitySettings,
+    pub collision_mode: CollisionMode,
+    /// Coefficient of restitution for [`CollisionMode::Elastic`], and the share of the
+    /// impact energy the pieces of a [`CollisionMode::Fragment`] impact carry away.
+    pub restitution: f32,
+    /// Friction coefficient for glancing [`CollisionMode::Elastic`] impacts.
+    pub friction: f32,
+    pub fragmentation: Fragmentation,
+    pub boundary: Boundary,
+    /// Half-width and half-height of the world box, centered on the origin.
+    pub boundary_half_extent: Vector2<f32>,
+    /// Change through [`SimState::set_radius_mode`] so existing bodies follow.
+    pub radius_mode: RadiusMode,
+    pub record_trails: bool,
+    pub trail_length: usize,
+    /// Net acceleration of every body at the start of the last step, following the bodies
+    /// through any removals during that step.
+    pub accelerations: Vec<Vector2<f32>>,
+    /// Simulated seconds since the scenario was built.
+    pub sim_time: f32,
+    /// Steps taken since the scenario was built.
+    pub step_count: u64,
+    /// Panic on the first non-finite position or velocity instead of removing the body.
+    /// Only honored in debug builds, where a backtrace points at the culprit.
+    pub strict_numerics: bool,
+    /// Newest first, capped at [`EVENT_LOG_LEN`].
+    pub events: VecDeque<SimEvent>,
+    /// How many entries at the front of `events` the last step added.
+    pub recent_events: usize,
+    /// Pairs overlapping after the last pass-through step, so a pass is logged once.
+    passing_pairs: Vec<[usize; 2]>,
+    pub ejection_distance: f32,
+    /// Remove ejected bodies instead of simulating them forever.
+    pub cull_ejected: bool,
+    /// Bodies ejected since the scenario was built, culled or not.
+    pub ejected_count: usize,
+    /// Speeds above this are scaled back down after each integration; `None` is unlimited.
+    pub max_speed: Option<f32>,
+    /// Drag coefficient: each step scales velocities by `1 - damping * dt`, so the system
+    /// loses energy by design and can settle into clumps. `None` is no drag.
+    pub damping: Option<f32>,
+    /// Id the next new body gets; above the id of every body in `bodies`.
+    next_id: u64,
+}
+
+impl SimState {
+    pub fn new(bodies: Vec<CelestialBody>) -> Self {
+        let mut sim = Self {
+            bodies,
+            integrator: Integrator::SemiImplicitEuler,
+            gravity: GravitySettings::default(),
+            collision_mode: CollisionMode::Merge,
+            restitution: 1.0,
+            friction: 0.0,
+            fragmentation: Fragmentation::default(),
+            boundary: Boundary::Open,
+            boundary_half_extent: Vector2::repeat(DEFAULT_BOUNDARY_HALF_EXTENT),
+            radius_mode: RadiusMode::Area,
+            record_trails: false,
+            trail_length: DEFAULT_TRAIL_LENGTH,
+            accelerations: Vec::new(),
+            sim_time: 0.0,
+            step_count: 0,
+            strict_numerics: false,
+            events: VecDeque::new(),
+            recent_events: 0,
+            passing_pairs: Vec::new(),
+            ejection_distance: DEFAULT_EJECTION_DISTANCE,
+            cull_ejected: false,
+            ejected_count: 0,
+            max_speed: None,
+            damping: None,
+            next_id: 0,
+        };
+        sim.number_bodies();
+        sim
+    }
+
+    /// Gives the bodies ids counting up from 0, as at the start of a run.
+    fn number_bodies(&mut self) {
+        for (id, body) in self.bodies.iter_mut().enumerate() {
+            body.id = id as u64;
+        }
+        self.next_id = self.bodies.len() as u64;
+    }
+
+    /// Puts back an earlier body list, such as an undo snapshot, and returns the current
+    /// one. Bodies keep their ids, and new ones are numbered past every restored id.
+    pub fn swap_bodies(&mut self, bodies: Vec<CelestialBody>) -> Vec<CelestialBody> {
+        let restored_next = bodies.iter().map(|b| b.id + 1).max().unwrap_or(0);
+        self.next_id = self.next_id.max(restored_next);
+        std::mem::replace(&mut self.bodies, bodies)
+    }
+
+    /// Starts a new run with `bodies`, clearing the clock, the event log and everything
+    /// else left from the previous run while keeping every setting.
+    pub fn reset(&mut self, bodies: Vec<CelestialBody>) {
+        self.bodies = bodies;
+        self.number_bodies();
+        self.accelerations.clear();
+        self.sim_time = 0.0;
+        self.step_count = 0;
+        self.events.clear();
+        self.recent_events = 0;
+        self.passing_pairs.clear();
+        self.ejected_count = 0;
+    }
+
+    pub fn bodies(&self) -> &[CelestialBody] {
+        &self.bodies
+    }
+
+    pub fn body(&self, index: usize) -> Option<&CelestialBody> {
+        self.bodies.get(index)
+    }
+
+    pub fn body_mut(&mut self, index: usize) -> Option<&mut CelestialBody> {
+        self.bodies.get_mut(index)
+    }
+
+    /// Adds a user-created body, sized for the current [`RadiusMode`].
+    pub fn add_body(&mut self, mut body: CelestialBody) {
+        body.resize(self.radius_mode);
+        body.id = self.next_id;
+        self.next_id += 1;
+        self.bodies.push(body);
+    }
+
+    /// Switches the radius mode and resizes every body to match it.
+    pub fn set_radius_mode(&mut self, mode: RadiusMode) {
+        self.radius_mode = mode;
+        for body in &mut self.bodies {
+            body.resize(mode);
+        }
+    }
+
+    /// Shortest `distance / closing speed` over all pairs heading towards each other:
+    /// roughly how soon the closest encounter happens. Pairs moving apart never count.
+    ///
+    /// Only encounters sooner than `horizon` are searched for. No two bodies close faster
+    /// than twice the top speed, so pairs further apart than that covers in `horizon` are
+    /// skipped with a spatial hash, and any result of `horizon` or more only means nothing
+    /// comes sooner. Infinite when nothing is moving.
+    pub fn min_approach_time(&self, horizon: f32) -> f32 {
+        let top_speed = self.bodies.iter().map(|b| b.vel.norm()).fold(0.0, f32::max);
+        if top_speed == 0.0 {
+            return f32::INFINITY;
+        }
+        let mut min = horizon;
+        let reach = 2.0 * top_speed * horizon;
+        for [i, j] in SpatialHash::with_cell_size(&self.bodies, reach).candidate_pairs() {
+            let (a, b) = (&self.bodies[i], &self.bodies[j]);
+            let offset = b.pos - a.pos;
+            let dist = offset.norm();
+            let closing = -offset.dot(&(b.vel - a.vel)) / dist;
+            if closing > 0.0 {
+                min = min.min(dist / closing);
+            }
+        }
+        min
+    }
+
+    /// Advances every body by `dt` and resolves collisions and ejections.
+    ///
+    /// Returns the new index of every old body if any were removed, or `None` when
+    /// indices are unchanged.
+    pub fn step(&mut self, dt: f32) -> Option<Vec<Option<usize>>> {
+        self.accelerations = self.integrator.step(&mut self.bodies, dt, &self.gravity);
+        self.sim_time += dt;
+        self.step_count += 1;
+        let mut events = Vec::new();
+        let mut remap = self.scrub_diverged(&mut events);
+        if let Some(damping) = self.damping {
+            let factor = (1.0 - damping * dt).max(0.0);
+            for body in &mut self.bodies {
+                body.vel *= factor;
+            }
+        }
+        if let Some(max_speed) = self.max_speed {
+            for body in &mut self.bodies {
+                let speed = body.vel.norm();
+                if speed > max_speed {
+                    body.vel *= max_speed / speed;
+                }
+            }
+        }
+        self.apply_boundary();
+        if self.record_trails {
+            for body in &mut self.bodies {
+                body.record_trail(self.trail_length);
+            }
+        }
+        match self.collision_mode {
+            CollisionMode::PassThrough => {
+                let pairs = overlapping_pairs(&self.bodies);
+                for &pair in &pairs {
+                    if self.passing_pairs.binary_search(&pair).is_err() {
+                        events.push(SimEvent {
+                            time: self.sim_time,
+                            bodies: pair.to_vec(),
+                            kind: SimEventKind::ClosePass,
+                        });
+                    }
+                }
+                self.passing_pairs = pairs;
+            }
+            CollisionMode::Merge => {
+                let merged = merge_collisions(
+                    &mut self.bodies,
+                    self.radius_mode,
+                    self.sim_time,
+                    &mut events,
+                );
+                remap = Some(compose_remaps(remap, merged));
+            }
+            CollisionMode::Fragment => {
+                let fragmented = fragment_collisions(
+                    &mut self.bodies,
+                    self.radius_mode,
+                    self.restitution,
+                    &self.fragmentation,
+                    self.sim_time,
+                    &mut events,
+                );
+                // Pieces past the survivors are new bodies, not copies of the one that broke.
+                let survivors = fragmented.iter().flatten().count();
+                for piece in &mut self.bodies[survivors..] {
+                    piece.id = self.next_id;
+                    self.next_id += 1;
+                }
+                remap = Some(compose_remaps(remap, fragmented));
+            }
+            CollisionMode::Elastic => resolve_elastic_collisions(
+                &mut self.bodies,
+                self.restitution,
+                self.friction,
+                self.sim_time,
+                &mut events,
+            ),
+        }
+        if let Some(culled) = self.detect_ejections(&mut events) {
+            remap = Some(compose_remaps(remap, culled));
+        }
+        self.log_events(events);
+        // Collision passes always report a remap; only hand it on if a body actually moved.
+        let remap =
+            remap.filter(|remap| remap.iter().enumerate().any(|(old, &new)| new != Some(old)));
+        if let Some(remap) = &remap {
+            let mut kept = vec![Vector2::zeros(); self.bodies.len()];
+            for (acc, new) in self.accelerations.iter().zip(remap) {
+                if let Some(new) = *new {
+                    kept[new] = *acc;
+                }
+            }
+            self.accelerations = kept;
+        }
+        // Fragments appended after the old bodies start out without an acceleration.
+        self.accelerations
+            .resize(self.bodies.len(), Vector2::zeros());
+        remap
+    }
+
+    /// Keeps every moving body inside the world box according to [`Self::boundary`].
+    fn apply_boundary(&mut self) {
+        let (min, max) = (-self.boundary_half_extent, self.boundary_half_extent);
+        for body in self.bodies.iter_mut().filter(|b| !b.fixed) {
+            match self.boundary {
+                Boundary::Open => {}
+                Boundary::Wrap => {
+                    let wrapped = Vector2::new(
+                        wrap_coordinate(body.pos.x, min.x, max.x),
+                        wrap_coordinate(body.pos.y, min.y, max.y),
+                    );
+                    if wrapped != body.pos {
+                        body.pos = wrapped;
+                        // Otherwise the trail would streak across the whole box.
+                        body.trail.clear();
+                    }
+                }
+                Boundary::Reflect => {
+                    for k in 0..2 {
+                        (body.pos[k], body.vel[k]) =
+                            reflect_coordinate(body.pos[k], body.vel[k], min[k], max[k]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Flags bodies past `ejection_distance` from the barycenter that are moving outward
+    /// faster than the escape velocity of the rest of the system's mass, then culls them
+    /// if `cull_ejected` is set, returning the remap when any were removed.
+    fn detect_ejections(&mut self, events: &mut Vec<SimEvent>) -> Option<Vec<Option<usize>>> {
+        let total_mass: f32 = self.bodies.iter().map(|b| b.mass).sum();
+        if total_mass <= 0.0 {
+            return None;
+        }
+        let center = self.barycenter();
+        let center_vel = self
+            .bodies
+            .iter()
+            .map(|b| b.vel * b.mass)
+            .sum::<Vector2<f32>>()
+            / total_mass;
+
+        for (i, body) in self.bodies.iter_mut().enumerate() {
+            if body.ejected || body.fixed {
+                continue;
+            }
+            let offset = body.pos - center;
+            let dist = offset.norm();
+            if dist < self.ejection_distance {
+                continue;
+            }
+            let rel_vel = body.vel - center_vel;
+            let radial = rel_vel.dot(&offset) / dist;
+            let escape_speed =
+                (2.0 * self.gravity.gravity_constant * (total_mass - body.mass) / dist).sqrt();
+            if radial > 0.0 && rel_vel.norm() > escape_speed {
+                body.ejected = true;
+                self.ejected_count += 1;
+                events.push(SimEvent {
+                    time: self.sim_time,
+                    bodies: vec![i],
+                    kind: SimEventKind::Ejection,
+                });
+            }
+        }
+
+        (self.cull_ejected && self.bodies.iter().any(|b| b.ejected))
+            .then(|| self.retain_indices(|_, b| !b.ejected))
+    }
+
+    /// Removes bodies whose state became NaN or infinite before they can spread it to
+    /// everyone else through the next force pass, or panics under `strict_numerics`.
+    fn scrub_diverged(&mut self, events: &mut Vec<SimEvent>) -> Option<Vec<Option<usize>>> {
+        let finite = |b: &CelestialBody| b.pos.iter().chain(b.vel.iter()).all(|x| x.is_finite());
+        let mut any = false;
+        for (i, body) in self.bodies.iter().enumerate() {
+            if !finite(body) {
+                if cfg!(debug_assertions) && self.strict_numerics {
+                    panic!(
+                        "body #{i} diverged at step {} (t = {:.3}s): pos {:?}, vel {:?}",
+                        self.step_count, self.sim_time, body.pos, body.vel
+                    );
+                }
+                any = true;
+                events.push(SimEvent {
+                    time: self.sim_time,
+                    bodies: vec![i],
+                    kind: SimEventKind::Diverged,
+                });
+            }
+        }
+        any.then(|| self.retain_indices(|_, b| finite(b)))
+    }
+
+    /// Keeps only the bodies matching `keep`, returning the new index of every old body.
+    /// Keeps the bodies `keep` accepts, given each one's index, and returns the new index
+    /// of every old body, or `None` for the ones removed, to pass to whatever stores
+    /// body indices.
+    pub fn retain_indices(
+        &mut self,
+        keep: impl Fn(usize, &CelestialBody) -> bool,
+    ) -> Vec<Option<usize>> {
+        retain_with_remap(&mut self.bodies, keep)
+    }
+
+    fn log_events(&mut self, events: Vec<SimEvent>) {
+        self.recent_events = events.len().min(EVENT_LOG_LEN);
+        for event in events {
+            self.events.push_front(event);
+        }
+        self.events.truncate(EVENT_LOG_LEN);
+    }
+
+    pub fn barycenter(&self) -> Vector2<f32> {
+        let total: f32 = self.bodies.iter().map(|b| b.mass).sum();
+        if total <= 0.0 {
+            return Vector2::zeros();
+        }
+        self.bodies
+            .iter()
+            .map(|b| b.pos * b.mass)
+            .sum::<Vector2<f32>>()
+            / total
+    }
+
+    /// Velocity of the barycenter: total momentum over total mass.
+    pub fn barycenter_velocity(&self) -> Vector2<f32> {
+        let total: f32 = self.bodies.iter().map(|b| b.mass).sum();
+        if total <= 0.0 {
+            return Vector2::zeros();
+        }
+        self.bodies
+            .iter()
+            .map(|b| b.vel * b.mass)
+            .sum::<Vector2<f32>>()
+            / total
+    }
+
+    /// Total angular momentum about the barycenter, `Σ m (r × v)` with `r` measured from
+    /// the barycenter. Conserved by gravity alone, so drift points at the integrator,
+    /// collisions or the orbit assist.
+    pub fn total_angular_momentum(&self) -> f32 {
+        let center = self.barycenter();
+        self.bodies
+            .iter()
+            .map(|b| {
+                let r = b.pos - center;
+                b.mass * (r.x * b.vel.y - r.y * b.vel.x)
+            })
+            .sum()
+    }
+
+    /// Returns `(kinetic, potential, total)` energy of the whole system.
+    pub fn total_energy(&self) -> (f32, f32, f32) {
+        let kinetic: f32 = self
+            .bodies
+            .iter()
+            .map(|b| 0.5 * b.mass * b.vel.norm_squared())
+            .sum();
+
+        let mut potential = 0.0;
+        for (i, a) in self.bodies.iter().enumerate() {
+            for b in &self.bodies[i + 1..] {
+                let dist = (b.pos - a.pos).norm();
+                if dist > 0.0 {
+                    potential +=
+                        self.gravity.pair_potential(a.mass, b.mass, dist) * a.interaction_sign(b);
+                }
+            }
+        }
+        (kinetic, potential, kinetic + potential)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ecolor::Color32;
+
+    use super::*;
+
+    fn body(x: f32, y: f32, mass: f32) -> CelestialBody {
+        CelestialBody::new(Vector2::new(x, y), mass, Color32::WHITE)
+    }
+
+    fn momentum(bodies: &[CelestialBody]) -> Vector2<f32> {
+        bodies.iter().map(|b| b.vel * b.mass).sum()
+    }
+
+    #[test]
+    fn merging_equal_bodies_conserves_momentum() {
+        let mut a = body(0.0, 0.0, 50.0);
+        a.vel = Vector2::new(3.0, -1.0);
+        let mut b = body(1.0, 0.5, 50.0);
+        b.vel = Vector2::new(-7.0, 4.0);
+        let mut bodies = vec![a, b];
+        let before = momentum(&bodies);
+
+        let remap = merge_collisions(&mut bodies, RadiusMode::Area, 0.0, &mut Vec::new());
+
+        assert_eq!(remap, vec![Some(0), None]);
+        assert_eq!(bodies.len(), 1);
+        assert_eq!(bodies[0].mass, 100.0);
+        assert!((momentum(&bodies) - before).norm() < 1e-4);
+        assert!((bodies[0].pos - Vector2::new(0.5, 0.25)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn wrap_reenters_through_the_opposite_edge() {
+        assert_eq!(wrap_coordinate(5.0, -10.0, 10.0), 5.0);
+        assert_eq!(wrap_coordinate(12.0, -10.0, 10.0), -8.0);
+        assert_eq!(wrap_coordinate(-13.0, -10.0, 10.0), 7.0);
+        // Several box widths out still lands inside.
+        assert_eq!(wrap_coordinate(55.0, -10.0, 10.0), -5.0);
+        assert_eq!(wrap_coordinate(10.0, -10.0, 10.0), -10.0);
+    }
+
+    #[test]
+    fn reflect_mirrors_position_and_turns_velocity_inward() {
+        assert_eq!(reflect_coordinate(3.0, -2.0, -10.0, 10.0), (3.0, -2.0));
+        assert_eq!(reflect_coordinate(12.0, 4.0, -10.0, 10.0), (8.0, -4.0));
+        assert_eq!(reflect_coordinate(-11.0, -4.0, -10.0, 10.0), (-9.0, 4.0));
+        // Already heading back in: the velocity keeps its sign.
+        assert_eq!(reflect_coordinate(12.0, -4.0, -10.0, 10.0), (8.0, -4.0));
+        // Overshooting past the far wall is clamped to it.
+        assert_eq!(reflect_coordinate(35.0, 1.0, -10.0, 10.0), (-10.0, -1.0));
+    }
+
+    #[test]
+    fn reflect_boundary_keeps_bodies_in_the_box() {
+        let mut a = body(9.0, 0.0, 1.0);
+        a.vel = Vector2::new(50.0, -30.0);
+        let speed = a.vel.norm();
+        let mut sim = SimState::new(vec![a]);
+        sim.boundary = Boundary::Reflect;
+        let half = sim.boundary_half_extent;
+
+        for _ in 0..1000 {
+            sim.step(0.1);
+            let b = &sim.bodies[0];
+            assert!(b.pos.x.abs() <= half.x && b.pos.y.abs() <= half.y);
+        }
+        assert!((sim.bodies[0].vel.norm() - speed).abs() < 1e-3);
+    }
+
+    #[test]
+    fn head_on_elastic_collision_swaps_equal_velocities() {
+        let mut a = body(0.0, 0.0, 10.0);
+        a.vel = Vector2::new(4.0, 0.0);
+        let mut b = body(a.radius * 1.5, 0.0, 10.0);
+        b.vel = Vector2::new(-1.0, 0.0);
+        let mut bodies = vec![a, b];
+
+        resolve_elastic_collisions(&mut bodies, 1.0, 0.0, 0.0, &mut Vec::new());
+
+        assert!((bodies[0].vel - Vector2::new(-1.0, 0.0)).norm() < 1e-5);
+        assert!((bodies[1].vel - Vector2::new(4.0, 0.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn friction_leaves_head_on_collisions_alone() {
+        let collide = |friction| {
+            let mut a = body(0.0, 0.0, 10.0);
+            a.vel = Vector2::new(3.0, 4.0);
+            let mut b = body(a.radius * 0.9, a.radius * 1.2, 30.0);
+            b.vel = Vector2::new(-1.5, -2.0);
+            let mut bodies = vec![a, b];
+            resolve_elastic_collisions(&mut bodies, 0.5, friction, 0.0, &mut Vec::new());
+            [bodies[0].vel, bodies[1].vel]
+        };
+        let smooth = collide(0.0);
+        let rough = collide(1.0);
+
+        assert!((smooth[0] - rough[0]).norm() < 1e-5);
+        assert!((smooth[1] - rough[1]).norm() < 1e-5);
+        // The bounce still happened.
+        assert!(smooth[0].dot(&Vector2::new(3.0, 4.0)) < 0.0);
+    }
+
+    #[test]
+    fn step_reports_a_remap_only_when_bodies_go() {
+        let mut sim = SimState::new(vec![body(0.0, 0.0, 10.0), body(100.0, 0.0, 10.0)]);
+        assert_eq!(sim.collision_mode, CollisionMode::Merge);
+        assert_eq!(sim.step(0.01), None);
+
+        sim.bodies[1].pos.x = 1.0;
+        assert_eq!(sim.step(0.01), Some(vec![Some(0), None]));
+    }
+
+    #[test]
+    fn ids_stay_with_bodies_and_are_never_reused() {
+        let mut sim = SimState::new(vec![
+            body(0.0, 0.0, 10.0),
+            body(1.0, 0.0, 10.0),
+            body(500.0, 0.0, 10.0),
+        ]);
+        sim.step(0.001);
+        let ids: Vec<u64> = sim.bodies.iter().map(|b| b.id).collect();
+        assert_eq!(ids, [0, 2]);
+
+        sim.add_body(body(-500.0, 0.0, 10.0));
+        assert_eq!(sim.bodies[2].id, 3);
+
+        // Shatter the survivor of the merge against the far body.
+        sim.collision_mode = CollisionMode::Fragment;
+        sim.bodies[1].pos = sim.bodies[0].pos + Vector2::new(1.0, 0.0);
+        sim.bodies[1].vel.x = -1000.0;
+        sim.step(0.001);
+        assert_eq!(sim.bodies[0].id, 0);
+        assert_eq!(sim.bodies[1].id, 3);
+        let debris: Vec<u64> = sim.bodies[2..].iter().map(|b| b.id).collect();
+        assert!(!debris.is_empty());
+        assert_eq!(debris, (4..4 + debris.len() as u64).collect::<Vec<_>>());
+
+        let mut restored = vec![body(0.0, 0.0, 1.0); 2];
+        restored[1].id = 40;
+        sim.swap_bodies(restored);
+        sim.add_body(body(10.0, 0.0, 1.0));
+        assert_eq!(sim.bodies[2].id, 41);
+    }
+
+    #[test]
+    fn runaway_bodies_do_not_poison_the_rest() {
+        let mut fast = body(200.0, 0.0, 10.0);
+        fast.vel = Vector2::new(f32::MAX, f32::MAX);
+        let mut broken = body(-200.0, 0.0, 10.0);
+        broken.pos.y = f32::NAN;
+        let mut sim = SimState::new(vec![
+            body(0.0, 0.0, 1000.0),
+            fast,
+            broken,
+            body(0.0, 100.0, 10.0),
+        ]);
+        sim.max_speed = Some(100.0);
+
+        for _ in 0..100 {
+            sim.step(0.01);
+        }
+
+        assert_eq!(sim.bodies.len(), 3);
+        assert!(
+            sim.events
+                .iter()
+                .any(|e| e.kind == SimEventKind::Diverged && e.bodies == [2])
+        );
+        for body in &sim.bodies {
+            assert!(body.pos.iter().chain(&body.vel).all(|x| x.is_finite()));
+            assert!(body.vel.norm() <= 100.0 * (1.0 + 1e-6));
+        }
+    }
+
+    #[test]
+    fn approach_time_counts_only_closing_pairs() {
+        let mut a = body(0.0, 0.0, 1.0);
+        let mut b = body(10.0, 0.0, 1.0);
+        a.vel.x = -1.0;
+        b.vel.x = 1.0;
+        let mut sim = SimState::new(vec![a, b]);
+        assert_eq!(sim.min_approach_time(100.0), 100.0);
+
+        sim.bodies[0].vel.x = 1.0;
+        sim.bodies[1].vel.x = -1.0;
+        assert!((sim.min_approach_time(100.0) - 5.0).abs() < 1e-5);
+        // Too far off to be searched for within the horizon.
+        assert_eq!(sim.min_approach_time(1.0), 1.0);
+    }
+
+    #[test]
+    fn same_seed_runs_are_bitwise_identical() {
+        let params = crate::ScenarioParams {
+            cluster_count: 40,
+            ..Default::default()
+        };
+        let run = || {
+            let mut sim = SimState::new(crate::Scenario::Cluster.bodies(5, &params));
+            for _ in 0..1000 {
+                sim.step(0.01);
+            }
+            sim.bodies
+                .iter()
+                .flat_map(|b| [b.pos.x, b.pos.y, b.vel.x, b.vel.y, b.mass])
+                .map(f32::to_bits)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use nalgebra::Vector2;
+
+use crate::CelestialBody;
+
+/// Cells are this many times the largest radius across. Two bodies can only overlap when
+/// their centers are closer than the sum of their radii, so with cells at least one
+/// diameter wide every overlapping pair lands in the same or adjacent cells.
+const CELL_SIZE_RADII: f32 = 2.0;
+
+/// Neighbors visited from each cell besides itself. Together with their mirror images
+/// these cover all eight surrounding cells, so each pair of cells is checked once.
+const FORWARD_NEIGHBORS: [(i64, i64); 4] = [(1, -1), (1, 0), (1, 1), (0, 1)];
+
+/// Uniform grid bucketing body indices by position, rebuilt every step to find bodies
+/// close enough to collide without testing every pair.
+pub struct SpatialHash {
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl SpatialHash {
+    /// Buckets every body, sizing the cells from the largest radius.
+    pub fn build(bodies: &[CelestialBody]) -> Self {
+        let max_radius = bodies.iter().map(|b| b.radius).fold(0.0, f32::max);
+        Self::with_cell_size(bodies, max_radius * CELL_SIZE_RADII)
+    }
+
+    /// Buckets every body into cells `cell_size` wide, so every pair closer than that
+    /// shows up among the candidates.
+    pub fn with_cell_size(bodies: &[CelestialBody], cell_size: f32) -> Self {
+        // Point-sized bodies never overlap; any positive size keeps the math finite.
+        let cell_size = cell_size.max(f32::MIN_POSITIVE);
+        let mut cells: HashMap<_, Vec<usize>> = HashMap::new();
+        for (i, body) in bodies.iter().enumerate() {
+            cells
+                .entry(cell_of(body.pos, cell_size))
+                .or_default()
+                .push(i);
+        }
+        Self { cells }
+    }
+
+    /// Every pair sharing or bordering a cell, as `[lower, higher]` index in ascending
+    /// order. A superset of the overlapping pairs at the time the grid was built.
+    pub fn candidate_pairs(&self) -> Vec<[usize; 2]> {
+        let mut pairs = Vec::new();
+        for (&(x, y), members) in &self.cells {
+            for (k, &i) in members.iter().enumerate() {
+                for &j in &members[k + 1..] {
+                    pairs.push([i.min(j), i.max(j)]);
+                }
+            }
+            for (dx, dy) in FORWARD_NEIGHBORS {
+                let Some(others) = self.cells.get(&(x.wrapping_add(dx), y.wrapping_add(dy))) else {
+                    continue;
+                };
+                for &i in members {
+                    for &j in others {
+                        pairs.push([i.min(j), i.max(j)]);
+                    }
+                }
+            }
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+}
+
+fn cell_of(pos: Vector2<f32>, cell_size: f32) -> (i64, i64) {
+    (
+        (pos.x / cell_size).floor() as i64,
+        (pos.y / cell_size).floor() as i64,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use ecolor::Color32;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn finds_the_same_overlaps_as_brute_force() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let bodies: Vec<_> = (0..400)
+            .map(|_| {
+                let pos = Vector2::new(
+                    rng.random_range(-300.0..300.0),
+                    rng.random_range(-300.0..300.0),
+                );
+                CelestialBody::new(pos, rng.random_range(1.0..2000.0), Color32::WHITE)
+            })
+            .collect();
+
+        let mut brute = Vec::new();
+        for i in 0..bodies.len() {
+            for j in i + 1..bodies.len() {
+                if bodies[i].overlaps(&bodies[j]) {
+                    brute.push([i, j]);
+                }
+            }
+        }
+        let hashed: Vec<_> = SpatialHash::build(&bodies)
+            .candidate_pairs()
+            .into_iter()
+            .filter(|&[i, j]| bodies[i].overlaps(&bodies[j]))
+            .collect();
+
+        assert!(!brute.is_empty());
+        assert_eq!(hashed, brute);
+    }
+}